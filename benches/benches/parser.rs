@@ -0,0 +1,45 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use x1337x_scraper::{parse_torrent_html, COMMENT_COUNT_SELECTOR};
+
+const NOW: u64 = 1_700_000_000;
+
+/// The page the parser tests use, with a single file and no comments
+const PAGE: &str = include_str!("../../test.html");
+
+/// Replaces the file list of the page by `count` files
+fn with_files(count: usize) -> String {
+    let start = PAGE.find("<h2>Files: </h2>").expect("file list heading") + "<h2>Files: </h2>".len();
+    let end = start + PAGE[start..].find("</ul>").expect("end of file list") + "</ul>".len();
+    let files = (0..count).map(|i| format!("<li><i class=\"flaticon-movies\"></i>Episode.{i:04}.1080p.WEBRip.x265.mkv ({}.{} GB)</li>\n", i % 4, i % 10)).collect::<String>();
+    format!("{}<ul>\n{files}</ul>{}", &PAGE[..start], &PAGE[end..])
+}
+
+/// Fills the comments tab of the page with `count` comments, as rendered for visitors
+fn with_comments(count: usize) -> String {
+    let comments = (0..count)
+        .map(|i| format!("<div class=\"comment-detail\"><h4><a href=\"/user/user{i}/\">user{i}</a></h4><span class=\"time\">{i} hours ago</span><p>Thanks for the upload, comment number {i} &amp; <b>more</b></p></div>\n"))
+        .collect::<String>();
+    PAGE.replace("<h2>There are currently no comments. Feel free to leave one :)</h2>", &comments)
+        .replace("Comments<span class=\"active\">0</span>", &format!("Comments<span class=\"active\">{count}</span>"))
+}
+
+fn parser(c: &mut Criterion) {
+    let fixtures = [
+        ("single file", PAGE.to_string()),
+        ("100 files", with_files(100)),
+        ("5000 files", with_files(5000)),
+        ("100 comments", with_comments(100)),
+        ("2000 comments", with_comments(2000)),
+    ];
+    let mut group = c.benchmark_group("parse_torrent_html");
+    for (name, page) in &fixtures {
+        group.throughput(Throughput::Bytes(page.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), page, |b, page| {
+            b.iter(|| parse_torrent_html(black_box(page), NOW, COMMENT_COUNT_SELECTOR).unwrap().unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parser);
+criterion_main!(benches);
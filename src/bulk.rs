@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use anyhow::bail;
+use log::*;
+use serde::Deserialize;
+use crate::{bytesize::ByteSize, clock::Clock, config::Config, http::HttpClient, infohash::InfoHash, lenient_number, parse_data_size, TorrentInfo};
+
+/// A torrent as listed by a bulk endpoint, which only gives the metadata of the detail page
+#[derive(Debug, Deserialize)]
+struct BulkTorrent {
+    #[serde(default, deserialize_with = "lenient_number")]
+    id: Option<usize>,
+    name: String,
+    #[serde(default)]
+    description: String,
+    infohash: String,
+    #[serde(default)]
+    category: String,
+    #[serde(default, rename = "type")]
+    ty: String,
+    #[serde(default)]
+    language: String,
+    /// In bytes, or a size like "1.3 GB"
+    #[serde(default, deserialize_with = "lenient_size")]
+    size: Option<u64>,
+    #[serde(default)]
+    uploader: String,
+    #[serde(default, deserialize_with = "lenient_number")]
+    downloads: Option<usize>,
+    #[serde(default, deserialize_with = "lenient_number")]
+    seeders: Option<usize>,
+    #[serde(default, deserialize_with = "lenient_number")]
+    leechers: Option<usize>,
+    /// Unix timestamp of the upload
+    #[serde(default, deserialize_with = "lenient_number")]
+    uploaded: Option<u64>,
+    /// Unix timestamp of the last tracker check
+    #[serde(default, deserialize_with = "lenient_number")]
+    last_checked: Option<u64>,
+}
+
+fn lenient_size<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(number) => number.as_u64(),
+        serde_json::Value::String(string) => string.trim().parse().ok().or_else(|| parse_data_size(string.trim())),
+        _ => None,
+    })
+}
+
+impl BulkTorrent {
+    fn into_torrent(self, now: u64) -> TorrentInfo {
+        let mut warnings = Vec::new();
+        let infohash = match self.infohash.parse::<InfoHash>() {
+            Ok(infohash) => infohash,
+            Err(err) => {
+                crate::record_warning(&mut warnings, err.to_string());
+                InfoHash::raw(self.infohash)
+            }
+        };
+        let mut torrent = TorrentInfo {
+            name: self.name,
+            description: self.description,
+            infohash,
+            category: self.category,
+            ty: self.ty,
+            language: self.language,
+            total_size: ByteSize::from(self.size.unwrap_or_default()),
+            uploader: self.uploader,
+            uploader_url: None,
+            downloads: self.downloads.unwrap_or_default(),
+            last_checked_ts: self.last_checked.unwrap_or(now),
+            peers_checked_ts: None,
+            uploaded_ts: self.uploaded.unwrap_or_default(),
+            seeders: self.seeders.unwrap_or_default(),
+            leechers: self.leechers.unwrap_or_default(),
+            scraped_ts: now,
+            tmdb_id: None,
+            series_id: None,
+            images: Vec::new(),
+            trackers: Vec::new(),
+            web_seeds: Vec::new(),
+            files: Vec::new(),
+            comments: Vec::new(),
+            page_magnet: None,
+            page_hash: 0,
+            warnings,
+            file_count: None,
+            comment_count: 0,
+            comment_count_unknown: false,
+            comments_incomplete: false,
+            verified: false,
+            next_refresh_ts: 0,
+            comments_scraped_ts: 0,
+            magnet_link: None,
+            scrape_duration_ms: None,
+            linked_ids: Vec::new(),
+        };
+        torrent.schedule_refresh();
+        torrent.fill_magnet_link();
+        torrent
+    }
+}
+
+/// Parses the JSON list of a bulk endpoint, keyed by torrent id
+fn parse_bulk(body: &str, now: u64) -> Result<BTreeMap<usize, TorrentInfo>, anyhow::Error> {
+    let mut torrents = BTreeMap::new();
+    for bulk_torrent in serde_json::from_str::<Vec<BulkTorrent>>(body)? {
+        let Some(id) = bulk_torrent.id else {
+            warn!("Ignoring bulk entry without a valid id: {}", bulk_torrent.name);
+            continue;
+        };
+        torrents.insert(id, bulk_torrent.into_torrent(now));
+    }
+    Ok(torrents)
+}
+
+/// Scrapes ids by batches through a bulk endpoint, for mirrors that offer one.
+///
+/// Bulk entries carry no files nor comments, which `refill` can fetch from the pages later.
+/// Ids missing from a response are taken as not holding a torrent.
+#[derive(Default)]
+pub struct BulkScraper {
+    /// Set when the mirror turns out not to support bulk requests
+    unsupported: bool,
+    /// Whether a bulk request ever succeeded, after which failures are regular errors
+    confirmed: bool,
+    torrents: BTreeMap<usize, TorrentInfo>,
+    /// Ids covered by the last bulk response
+    fetched: std::ops::Range<usize>,
+}
+
+impl BulkScraper {
+    /// Tells whether an id is covered by the last bulk response, and so scraped without a request
+    pub fn serves(&self, id: usize) -> bool {
+        !self.unsupported && self.fetched.contains(&id)
+    }
+
+    /// Scrapes an id from a bulk response, fetching the batch starting at it when needed.
+    /// Returns `None` when the mirror doesn't support bulk requests and the page should be scraped instead.
+    pub fn scrape(&mut self, config: &Config, client: &dyn HttpClient, clock: &dyn Clock, id: usize) -> Option<Result<Option<TorrentInfo>, anyhow::Error>> {
+        if self.unsupported {
+            return None;
+        }
+        if !self.serves(id) {
+            match self.fetch(config, client, clock, id) {
+                Ok(()) => self.confirmed = true,
+                Err(err) if !self.confirmed => {
+                    warn!("Bulk requests don't work on this mirror, scraping pages instead: {err}");
+                    self.unsupported = true;
+                    return None;
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        let torrent = self.torrents.remove(&id);
+        if let Some(Err(err)) = torrent.as_ref().map(|torrent| crate::check_strict(config, torrent)) {
+            return Some(Err(err));
+        }
+        Some(Ok(torrent))
+    }
+
+    fn fetch(&mut self, config: &Config, client: &dyn HttpClient, clock: &dyn Clock, start: usize) -> Result<(), anyhow::Error> {
+        let end = start + config.bulk_size;
+        let ids = (start..end).map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+        let url = format!("{}{}", config.mirror_for(start), config.bulk_path.replace("{ids}", &ids));
+        let resp = client.get(&url)?;
+        if resp.status_code != 200 {
+            bail!("Unexpected status code for bulk request: {}", resp.status_code);
+        }
+        self.torrents = parse_bulk(&resp.text(), clock.now())?;
+        self.torrents.retain(|id, _| (start..end).contains(id));
+        self.fetched = start..end;
+        debug!("Fetched {} torrents in bulk for ids {start} to {}", self.torrents.len(), end - 1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::FixedClock, fixture_server::{FixtureServer, Route}, http::{ClientSettings, MinreqClient}};
+
+    const NOW: u64 = 1_700_000_000;
+    const HASH: &str = "4738fb09f6ae3e2f45bca0ffec9f32779500ab03";
+
+    fn client() -> MinreqClient {
+        MinreqClient { settings: ClientSettings::default() }
+    }
+
+    #[test]
+    fn parses_lenient_entries() {
+        let body = format!(r#"[
+            {{"id": "10", "name": "a", "infohash": "{HASH}", "size": "1.5 KB", "seeders": "12", "uploaded": 1600000000}},
+            {{"id": 11, "name": "b", "infohash": "nope", "size": 2048, "leechers": "n/a"}},
+            {{"id": null, "name": "c", "infohash": "{HASH}"}}
+        ]"#);
+        let torrents = parse_bulk(&body, NOW).unwrap();
+        assert_eq!(torrents.keys().collect::<Vec<_>>(), [&10, &11]);
+        assert_eq!(torrents[&10].total_size, ByteSize::from(1536));
+        assert_eq!(torrents[&10].seeders, 12);
+        assert_eq!(torrents[&10].uploaded_ts, 1_600_000_000);
+        assert_eq!(torrents[&10].last_checked_ts, NOW);
+        assert!(torrents[&10].magnet_link.is_some());
+        assert_eq!(torrents[&11].leechers, 0);
+        assert!(!torrents[&11].infohash.is_valid());
+        assert_eq!(torrents[&11].warnings.len(), 1);
+        assert!(parse_bulk("{}", NOW).is_err());
+    }
+
+    #[test]
+    fn scrapes_batches_and_falls_back_to_pages() {
+        let body = format!(r#"[{{"id": 10, "name": "a", "infohash": "{HASH}"}}, {{"id": 12, "name": "out of range", "infohash": "{HASH}"}}]"#);
+        let server = FixtureServer::start([("/api/torrents?ids=10,11", Route::ok(body))]);
+        let config = Config { base_url: server.url(), bulk_size: 2, ..Config::default() };
+        let mut bulk = BulkScraper::default();
+
+        assert_eq!(bulk.scrape(&config, &client(), &FixedClock(NOW), 10).unwrap().unwrap().unwrap().name, "a");
+        assert!(bulk.serves(11));
+        assert!(bulk.scrape(&config, &client(), &FixedClock(NOW), 11).unwrap().unwrap().is_none());
+        assert_eq!(server.requests().len(), 1);
+        // Failures after a successful batch are errors rather than a sign of no support
+        assert!(bulk.scrape(&config, &client(), &FixedClock(NOW), 12).unwrap().is_err());
+
+        let mut unsupported = BulkScraper::default();
+        assert!(unsupported.scrape(&config, &client(), &FixedClock(NOW), 20).is_none());
+        assert!(!unsupported.serves(20));
+        assert!(unsupported.scrape(&config, &client(), &FixedClock(NOW), 10).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use std::{fmt, iter::Sum, ops::{Add, AddAssign, Sub}};
+use serde::{Serialize, Deserialize};
+
+/// Units of `human_size`, each 1024 times the previous one like on the site
+const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+/// A number of bytes, serialized as a plain integer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ByteSize(u64);
+
+impl ByteSize {
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<u64> for ByteSize {
+    fn from(bytes: u64) -> Self {
+        ByteSize(bytes)
+    }
+}
+
+impl Add for ByteSize {
+    type Output = ByteSize;
+
+    fn add(self, other: ByteSize) -> ByteSize {
+        ByteSize(self.0.saturating_add(other.0))
+    }
+}
+
+/// Saturates at zero, as sizes can't be negative
+impl Sub for ByteSize {
+    type Output = ByteSize;
+
+    fn sub(self, other: ByteSize) -> ByteSize {
+        ByteSize(self.0.saturating_sub(other.0))
+    }
+}
+
+impl AddAssign for ByteSize {
+    fn add_assign(&mut self, other: ByteSize) {
+        *self = *self + other;
+    }
+}
+
+impl Sum for ByteSize {
+    fn sum<I: Iterator<Item = ByteSize>>(iter: I) -> ByteSize {
+        iter.fold(ByteSize::default(), Add::add)
+    }
+}
+
+/// Formats bytes the way the site does, such as "87.8 MB"
+pub fn human_size(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        unit => format!("{value:.1} {}", UNITS[unit]),
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&human_size(self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_like_the_site() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KB");
+        assert_eq!(human_size(92_065_382), "87.8 MB");
+        assert_eq!(human_size(5 << 40), "5.0 TB");
+        assert_eq!(human_size(5 << 50), "5120.0 TB");
+        assert_eq!(format!("{:>8}", ByteSize::from(512)), "   512 B");
+    }
+
+    #[test]
+    fn arithmetic_saturates() {
+        let total: ByteSize = [1, 2, 3].into_iter().map(ByteSize::from).sum();
+        assert_eq!(total, ByteSize::from(6));
+        assert_eq!(ByteSize::from(1) - ByteSize::from(2), ByteSize::default());
+        assert_eq!(ByteSize::from(u64::MAX) + ByteSize::from(1), ByteSize::from(u64::MAX));
+        assert_eq!(serde_json::to_string(&ByteSize::from(42)).unwrap(), "42");
+    }
+}
@@ -0,0 +1,252 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+/// Normalized site category, as shown in the "Category" field of torrent pages
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+    Movies,
+    Tv,
+    Games,
+    Music,
+    Apps,
+    Documentaries,
+    Anime,
+    Xxx,
+    Other,
+    /// A category we don't know about yet, kept verbatim
+    Unknown(String),
+}
+
+impl Category {
+    /// Maps a category label to its normalized form, ignoring case and surrounding whitespace
+    pub fn parse(value: &str) -> Category {
+        let value = value.trim();
+        match value.to_ascii_lowercase().as_str() {
+            "movies" | "movie" => Category::Movies,
+            "tv" | "television" => Category::Tv,
+            "games" | "game" => Category::Games,
+            "music" => Category::Music,
+            "apps" | "applications" => Category::Apps,
+            "documentaries" | "documentary" => Category::Documentaries,
+            "anime" => Category::Anime,
+            "xxx" | "adult" | "porn" => Category::Xxx,
+            "other" => Category::Other,
+            _ => Category::Unknown(value.to_string()),
+        }
+    }
+
+    /// Tells whether the category holds adult content
+    pub fn is_adult(&self) -> bool {
+        *self == Category::Xxx
+    }
+
+    /// Parses a comma-separated list such as "Movies,TV"
+    pub fn parse_list(value: &str) -> Vec<Category> {
+        value.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()).map(Category::parse).collect()
+    }
+}
+
+/// Names given to categories in exports, for consumers with their own taxonomy
+pub type CategoryMap = BTreeMap<Category, String>;
+
+/// Reads a JSON object mapping site categories to names, such as `{"Movies": "film", "TV": "tv"}`
+pub fn load_map(path: &Path) -> Result<CategoryMap, anyhow::Error> {
+    let map: BTreeMap<String, String> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(map.into_iter().map(|(category, name)| (Category::parse(&category), name)).collect())
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Category::Movies => write!(f, "Movies"),
+            Category::Tv => write!(f, "TV"),
+            Category::Games => write!(f, "Games"),
+            Category::Music => write!(f, "Music"),
+            Category::Apps => write!(f, "Apps"),
+            Category::Documentaries => write!(f, "Documentaries"),
+            Category::Anime => write!(f, "Anime"),
+            Category::Xxx => write!(f, "XXX"),
+            Category::Other => write!(f, "Other"),
+            Category::Unknown(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// Normalized "Type" field, whose possible values depend on the category
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Subcategory {
+    // Video
+    Hd,
+    Uhd,
+    Sd,
+    Dvd,
+    H264,
+    Hevc,
+    Divx,
+    Mp4,
+    ThreeD,
+    Dubs,
+    Bollywood,
+    Cartoons,
+    // Games
+    PcGame,
+    Ps2,
+    Ps3,
+    Ps4,
+    Psp,
+    Xbox,
+    Xbox360,
+    Switch,
+    Wii,
+    Ds,
+    // Music
+    Mp3,
+    Lossless,
+    Aac,
+    Album,
+    Discography,
+    Single,
+    Concerts,
+    Radio,
+    MusicVideo,
+    // Apps
+    PcSoftware,
+    Android,
+    Ios,
+    // Shared between games and apps
+    Mac,
+    Linux,
+    /// A type we don't know about for this category, kept verbatim
+    Other(String),
+}
+
+impl Subcategory {
+    /// Interprets a "Type" label in the context of its category
+    pub fn parse(category: &Category, ty: &str) -> Subcategory {
+        let ty = ty.trim();
+        let lowercase = ty.to_ascii_lowercase();
+        let subcategory = match (category, lowercase.as_str()) {
+            (Category::Movies | Category::Tv | Category::Documentaries | Category::Anime | Category::Xxx, label) => match label {
+                "hd" => Some(Subcategory::Hd),
+                "uhd" | "4k" => Some(Subcategory::Uhd),
+                "sd" => Some(Subcategory::Sd),
+                "dvd" => Some(Subcategory::Dvd),
+                "h.264/x264" | "x264" => Some(Subcategory::H264),
+                "hevc/x265" | "x265" => Some(Subcategory::Hevc),
+                "divx/xvid" => Some(Subcategory::Divx),
+                "mp4" => Some(Subcategory::Mp4),
+                "3d" => Some(Subcategory::ThreeD),
+                "dubs/dual audio" => Some(Subcategory::Dubs),
+                "bollywood" => Some(Subcategory::Bollywood),
+                "cartoons" => Some(Subcategory::Cartoons),
+                _ => None,
+            },
+            (Category::Games, label) => match label {
+                "pc game" | "pc" => Some(Subcategory::PcGame),
+                "ps2" => Some(Subcategory::Ps2),
+                "ps3" => Some(Subcategory::Ps3),
+                "ps4" => Some(Subcategory::Ps4),
+                "psp" => Some(Subcategory::Psp),
+                "xbox" => Some(Subcategory::Xbox),
+                "xbox360" | "xbox 360" => Some(Subcategory::Xbox360),
+                "switch" => Some(Subcategory::Switch),
+                "wii" => Some(Subcategory::Wii),
+                "ds" | "3ds" => Some(Subcategory::Ds),
+                "mac" => Some(Subcategory::Mac),
+                "linux" => Some(Subcategory::Linux),
+                _ => None,
+            },
+            (Category::Music, label) => match label {
+                "mp3" => Some(Subcategory::Mp3),
+                "lossless" => Some(Subcategory::Lossless),
+                "aac" => Some(Subcategory::Aac),
+                "album" => Some(Subcategory::Album),
+                "discography" | "box set" => Some(Subcategory::Discography),
+                "single" => Some(Subcategory::Single),
+                "concerts" => Some(Subcategory::Concerts),
+                "radio" => Some(Subcategory::Radio),
+                "video" => Some(Subcategory::MusicVideo),
+                "dvd" => Some(Subcategory::Dvd),
+                _ => None,
+            },
+            (Category::Apps, label) => match label {
+                "pc software" => Some(Subcategory::PcSoftware),
+                "mac" => Some(Subcategory::Mac),
+                "linux" => Some(Subcategory::Linux),
+                "android" => Some(Subcategory::Android),
+                "ios" => Some(Subcategory::Ios),
+                _ => None,
+            },
+            _ => None,
+        };
+        subcategory.unwrap_or_else(|| Subcategory::Other(ty.to_string()))
+    }
+}
+
+impl fmt::Display for Subcategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Subcategory::Hd => "HD",
+            Subcategory::Uhd => "UHD",
+            Subcategory::Sd => "SD",
+            Subcategory::Dvd => "DVD",
+            Subcategory::H264 => "h.264/x264",
+            Subcategory::Hevc => "HEVC/x265",
+            Subcategory::Divx => "Divx/Xvid",
+            Subcategory::Mp4 => "Mp4",
+            Subcategory::ThreeD => "3D",
+            Subcategory::Dubs => "Dubs/Dual Audio",
+            Subcategory::Bollywood => "Bollywood",
+            Subcategory::Cartoons => "Cartoons",
+            Subcategory::PcGame => "PC Game",
+            Subcategory::Ps2 => "PS2",
+            Subcategory::Ps3 => "PS3",
+            Subcategory::Ps4 => "PS4",
+            Subcategory::Psp => "PSP",
+            Subcategory::Xbox => "Xbox",
+            Subcategory::Xbox360 => "Xbox360",
+            Subcategory::Switch => "Switch",
+            Subcategory::Wii => "Wii",
+            Subcategory::Ds => "DS",
+            Subcategory::Mp3 => "MP3",
+            Subcategory::Lossless => "Lossless",
+            Subcategory::Aac => "AAC",
+            Subcategory::Album => "Album",
+            Subcategory::Discography => "Discography",
+            Subcategory::Single => "Single",
+            Subcategory::Concerts => "Concerts",
+            Subcategory::Radio => "Radio",
+            Subcategory::MusicVideo => "Video",
+            Subcategory::PcSoftware => "PC Software",
+            Subcategory::Android => "Android",
+            Subcategory::Ios => "iOS",
+            Subcategory::Mac => "Mac",
+            Subcategory::Linux => "Linux",
+            Subcategory::Other(value) => value,
+        };
+        write!(f, "{label}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_category_lists() {
+        assert_eq!(Category::parse_list(" movies, TV ,,Porn,Comics"), [Category::Movies, Category::Tv, Category::Xxx, Category::Unknown(String::from("Comics"))]);
+        assert_eq!(Category::parse(" Television ").to_string(), "TV");
+        assert_eq!(Category::parse("Comics").to_string(), "Comics");
+        assert!(Category::parse("adult").is_adult());
+    }
+
+    #[test]
+    fn parses_types_in_the_context_of_their_category() {
+        assert_eq!(Subcategory::parse(&Category::Movies, " x265 "), Subcategory::Hevc);
+        assert_eq!(Subcategory::parse(&Category::Music, "DVD"), Subcategory::Dvd);
+        assert_eq!(Subcategory::parse(&Category::Apps, "Mac"), Subcategory::Mac);
+        assert_eq!(Subcategory::parse(&Category::Games, "Mac"), Subcategory::Mac);
+        assert_eq!(Subcategory::parse(&Category::Music, "HD"), Subcategory::Other(String::from("HD")));
+        assert_eq!(Subcategory::parse(&Category::Other, "Ebooks").to_string(), "Ebooks");
+        assert_eq!(Subcategory::parse(&Category::Games, "xbox 360").to_string(), "Xbox360");
+    }
+}
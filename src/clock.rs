@@ -0,0 +1,37 @@
+/// Source of the current time, as a unix timestamp in seconds
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        chrono::Utc::now().timestamp() as u64
+    }
+}
+
+/// A clock stuck at a given time, for tests
+#[cfg(test)]
+pub struct FixedClock(pub u64);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A clock moving forward by `step` seconds every time it's read, for tests of code waiting on time
+#[cfg(test)]
+pub struct SteppingClock {
+    pub now: std::sync::atomic::AtomicU64,
+    pub step: u64,
+}
+
+#[cfg(test)]
+impl Clock for SteppingClock {
+    fn now(&self) -> u64 {
+        self.now.fetch_add(self.step, std::sync::atomic::Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,900 @@
+use std::{collections::BTreeMap, ops::RangeInclusive, path::{Path, PathBuf}, str::FromStr};
+use anyhow::{anyhow, bail};
+use chrono::{Local, NaiveDateTime, NaiveTime, TimeZone};
+use log::*;
+use crate::{category::Category, parse_data_size};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Command {
+    /// Linearly scan torrent ids, storing results in the stash
+    #[default]
+    Scan,
+    /// Write stashed torrents as NDJSON
+    Export,
+    /// Remove stale `None` entries from the stash
+    Prune,
+    /// Scrape stashed torrents again to update them
+    Refresh,
+    /// Scrape a single torrent from its URL and print it
+    ScrapeUrl,
+    /// Scrape a single torrent from its id and print it
+    Scrape,
+    /// Scrape again the torrents whose files or comments are missing
+    Refill,
+    /// Print how many ids the stash holds
+    Stats,
+    /// Write the infohashes of stashed torrents, one per line
+    ExportInfohashes,
+    /// Write the canonical URLs of stashed torrents, one per line
+    ExportUrls,
+    /// Write the infohashes of stashed torrents linked to a movie or series along with their media ids, as NDJSON
+    ExportMediaMap,
+    /// Check that a .torrent file matches the infohash of a stashed torrent
+    VerifyTorrent,
+    /// Scrape known torrents to check that the parser still matches the site
+    Selftest,
+    /// Print the stashed torrents ranking highest on a metric
+    Top,
+    /// Store magnet links in records scraped before they were
+    BackfillMagnets,
+    /// Recompute the fields derived from scraped data in all stashed torrents, without any request
+    Reindex,
+    /// Check that the magnet links of stashed torrents are well-formed, printing the invalid ones
+    ValidateMagnets,
+    /// Scrape again the torrents that failed according to a log
+    ReplayErrors,
+    /// Scrape the torrents listed on a trending page
+    ScrapeTrending,
+    /// Re-encode the chunks of a stash into another directory
+    Convert,
+    /// Rewrite stash chunks with consistent formatting
+    Compact,
+    /// Estimate how many live torrents a range of ids holds by sampling it
+    Probe,
+    /// Print the stashed torrents whose name contains a text
+    Find,
+    /// Scrape torrents breadth-first from seed ids, following the torrents their pages link to
+    Crawl,
+}
+
+impl Command {
+    /// Tells whether the command scrapes many torrents in a row, which inter-request delays apply to
+    pub fn crawls(self) -> bool {
+        matches!(self, Command::Scan | Command::Refresh | Command::Refill | Command::Selftest | Command::ReplayErrors | Command::ScrapeTrending | Command::Probe | Command::Crawl)
+    }
+}
+
+/// Periods the site ranks trending torrents over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrendingPeriod {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl FromStr for TrendingPeriod {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "day" => Ok(TrendingPeriod::Day),
+            "week" => Ok(TrendingPeriod::Week),
+            "month" => Ok(TrendingPeriod::Month),
+            value => Err(anyhow!("expected day, week or month, got {value}")),
+        }
+    }
+}
+
+/// Encodings of stash chunks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StashEncoding {
+    #[default]
+    Json,
+    /// MessagePack, about half the size of JSON
+    Msgpack,
+}
+
+impl FromStr for StashEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "json" => Ok(StashEncoding::Json),
+            "msgpack" => Ok(StashEncoding::Msgpack),
+            value => Err(anyhow!("expected json or msgpack, got {value}")),
+        }
+    }
+}
+
+/// HTTP clients requests can go through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpClientKind {
+    /// Keeps connections open between requests
+    #[default]
+    Pooled,
+    /// minreq, opening a connection per request
+    Minreq,
+}
+
+impl FromStr for HttpClientKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pooled" => Ok(HttpClientKind::Pooled),
+            "minreq" => Ok(HttpClientKind::Minreq),
+            value => Err(anyhow!("expected pooled or minreq, got {value}")),
+        }
+    }
+}
+
+/// Daily window outside of which scans pause, such as `01:00-06:00`.
+/// Times are local unless followed by `UTC`, and windows can wrap around midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub utc: bool,
+}
+
+impl ActiveHours {
+    /// Tells whether a unix timestamp falls within the window
+    pub fn contains(&self, ts: u64) -> bool {
+        let Some(utc) = NaiveDateTime::from_timestamp_opt(ts as i64, 0) else {
+            return true;
+        };
+        let time = match self.utc {
+            true => utc.time(),
+            false => Local.from_utc_datetime(&utc).time(),
+        };
+        match self.start <= self.end {
+            true => self.start <= time && time < self.end,
+            false => self.start <= time || time < self.end,
+        }
+    }
+}
+
+impl FromStr for ActiveHours {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (window, utc) = match value.trim().strip_suffix("UTC") {
+            Some(window) => (window.trim(), true),
+            None => (value.trim(), false),
+        };
+        let (start, end) = window.split_once('-').ok_or_else(|| anyhow!("expected a window like 01:00-06:00, got {value}"))?;
+        let parse_time = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|err| anyhow!("invalid time {time:?}: {err}"));
+        let (start, end) = (parse_time(start)?, parse_time(end)?);
+        if start == end {
+            bail!("the window {value} is empty");
+        }
+        Ok(ActiveHours { start, end, utc })
+    }
+}
+
+/// How single scraped torrents are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Compact JSON, on a single line
+    Json,
+    #[default]
+    Pretty,
+    /// A summary meant for humans
+    Text,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "json" => Ok(OutputFormat::Json),
+            "pretty" => Ok(OutputFormat::Pretty),
+            "text" => Ok(OutputFormat::Text),
+            value => Err(anyhow!("expected json, pretty or text, got {value}")),
+        }
+    }
+}
+
+/// Metrics torrents can be ranked by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RankBy {
+    #[default]
+    Seeders,
+    Size,
+    Downloads,
+}
+
+impl FromStr for RankBy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "seeders" => Ok(RankBy::Seeders),
+            "size" => Ok(RankBy::Size),
+            "downloads" => Ok(RankBy::Downloads),
+            value => Err(anyhow!("expected seeders, size or downloads, got {value}")),
+        }
+    }
+}
+
+/// Parts of a record that can be missing because of transient errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordPart {
+    Files,
+    Comments,
+}
+
+/// Optional fields of records that can be left out of the stash to save space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionalField {
+    Description,
+    Images,
+    Trackers,
+    Files,
+    Comments,
+    Warnings,
+}
+
+impl OptionalField {
+    fn parse_list(value: &str) -> Result<Vec<OptionalField>, anyhow::Error> {
+        value.split(',').map(|field| match field.trim() {
+            "description" => Ok(OptionalField::Description),
+            "images" => Ok(OptionalField::Images),
+            "trackers" => Ok(OptionalField::Trackers),
+            "files" => Ok(OptionalField::Files),
+            "comments" => Ok(OptionalField::Comments),
+            "warnings" => Ok(OptionalField::Warnings),
+            field => Err(anyhow!("Field {field} can't be omitted")),
+        }).collect()
+    }
+}
+
+impl RecordPart {
+    /// Returns the field holding the part
+    pub fn field(self) -> OptionalField {
+        match self {
+            RecordPart::Files => OptionalField::Files,
+            RecordPart::Comments => OptionalField::Comments,
+        }
+    }
+
+    fn parse_list(value: &str) -> Result<Vec<RecordPart>, anyhow::Error> {
+        value.split(',').map(|part| match part.trim() {
+            "files" => Ok(RecordPart::Files),
+            "comments" => Ok(RecordPart::Comments),
+            part => Err(anyhow!("Unknown record part: {part}")),
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub command: Command,
+    /// Address to serve OpenMetrics on, such as "127.0.0.1:9100"
+    pub metrics_addr: Option<String>,
+    /// Only store torrents from these categories, others are recorded as checked
+    pub only_categories: Option<Vec<Category>>,
+    /// Output file of exports
+    pub out: Option<PathBuf>,
+    /// Leave adult torrents out of scans and exports
+    pub exclude_nsfw: bool,
+    /// Don't store records that look broken, such as those without a name or with an invalid infohash
+    pub strict: bool,
+    /// JSON file renaming categories in exports
+    pub category_map: Option<PathBuf>,
+    /// Continue an interrupted export instead of starting over
+    pub resume: bool,
+    /// Age in days after which `None` entries are pruned
+    pub missing_older_than: Option<u64>,
+    /// Base delay between two torrents, raised to the mirrors' robots.txt `Crawl-delay` unless `ignore_robots` is set
+    pub delay_ms: u64,
+    /// Don't fetch the mirrors' robots.txt for their crawl delay
+    pub ignore_robots: bool,
+    /// Maximum random delay added on top of `delay_ms`
+    pub jitter_ms: u64,
+    /// Seed of the jitter RNG, for reproducible runs
+    pub seed: Option<u64>,
+    /// Timeout of every outbound request
+    pub timeout_secs: u64,
+    /// Times a request failing with a network error, 429 or 5xx is retried
+    pub retries: usize,
+    /// Retries allowed for the whole run, so that an outage doesn't multiply requests
+    pub max_total_retries: Option<u64>,
+    /// Redirects followed by each request, 0 to treat any redirect as an error
+    pub max_redirects: usize,
+    /// Torrent pages smaller than this are taken as load shedding and retried like failed requests, 0 to accept any page
+    pub min_page_bytes: u64,
+    /// Mirror to scrape, without trailing slash
+    pub base_url: String,
+    /// Mirrors to spread torrents over instead of `base_url`, without trailing slashes
+    pub mirrors: Vec<String>,
+    /// User-Agent header sent with requests
+    pub user_agent: Option<String>,
+    /// Cookies sent to the mirrors, as "name=value; name2=value2", completed by those the mirrors set during the run
+    pub cookie: Option<String>,
+    /// Client requests go through
+    pub http_client: HttpClientKind,
+    /// HTTP proxy requests go through, as `http://[user:password@]host:port`
+    pub proxy: Option<String>,
+    /// Path of the comments endpoint relative to `base_url`, where `{id}` is replaced by the torrent id.
+    /// With a `{page}` placeholder, pages are fetched from 1 until one is empty or all announced comments were fetched.
+    pub comments_path: String,
+    /// Path of the endpoint serving the file list items of torrents whose page leaves them out, where `{id}` is replaced by the torrent id
+    pub files_path: String,
+    /// CSS selector of the badge holding the comment count, which varies between mirrors
+    pub comment_count_selector: String,
+    /// Reuse stored comments fetched less than this many days ago when scraping a torrent again
+    pub comments_refresh_days: Option<u64>,
+    /// Store the comments JSON of scraped torrents in `stash/comments/`
+    pub keep_raw_comments: bool,
+    /// Torrent URL given to `scrape-url`
+    pub url: Option<String>,
+    /// Parts of records that `refill` looks for
+    pub missing: Vec<RecordPart>,
+    /// First id of the scan. When resuming from the cursor, the scan starts at whichever is higher.
+    pub start_id: usize,
+    /// Start the scan where the previous one stopped, as saved in `stash/cursor`
+    pub resume_from_cursor: bool,
+    /// File holding the highest id found to hold a torrent. Scans start right above it and keep it up to date, to only look for new torrents.
+    pub since_id_file: Option<PathBuf>,
+    /// Last id of the range that `probe` samples
+    pub end_id: Option<usize>,
+    /// Number of ids that `probe` scrapes
+    pub samples: usize,
+    /// Stop the scan after this many ids instead of running forever
+    pub count: Option<usize>,
+    /// Whether ids already in the stash count towards `count`
+    pub count_skipped: bool,
+    /// Stop scanning when the stash disk has less free space than this, in bytes
+    pub min_free_disk: Option<u64>,
+    /// Only store torrents at least this large, in bytes
+    pub min_size: Option<u64>,
+    /// Only store torrents at most this large, in bytes
+    pub max_size: Option<u64>,
+    /// Only export torrents with at least this many seeders
+    pub min_seeders: Option<usize>,
+    /// Torrent id for commands working on a single torrent
+    pub id: Option<usize>,
+    /// .torrent file given to `verify-torrent`
+    pub torrent_file: Option<PathBuf>,
+    /// Directory coordinating the request rate of processes scraping the same mirror
+    pub shared_ratelimit: Option<PathBuf>,
+    /// Combined requests per second allowed by the shared rate limiter
+    pub max_rps: f64,
+    /// Encoding of the chunks written to the stash
+    pub stash_encoding: StashEncoding,
+    /// Encoding `convert` expects chunks to be in, any when unset
+    pub from: Option<StashEncoding>,
+    /// Encoding `convert` writes chunks in
+    pub to: Option<StashEncoding>,
+    /// Stash directory read by `convert`
+    pub input: Option<PathBuf>,
+    /// Fields left out of stored records
+    pub omit_fields: Vec<OptionalField>,
+    /// Append results to logs next to the chunks instead of rewriting them, keeping every past result
+    pub append_only: bool,
+    /// Fields left out of the records of some categories, such as images of music, on top of `omit_fields`.
+    /// Comments and separately listed files omitted this way aren't fetched at all.
+    pub category_omit_fields: BTreeMap<Category, Vec<OptionalField>>,
+    /// Torrent ids for commands working on a few torrents
+    pub ids: Vec<usize>,
+    /// Scan through the bulk endpoint of the mirror when it has one
+    pub bulk: bool,
+    /// Path of the bulk endpoint relative to `base_url`, where `{ids}` is replaced by comma-separated ids
+    pub bulk_path: String,
+    /// Number of ids asked for in each bulk request
+    pub bulk_size: usize,
+    /// Hours of the day when scans run, pausing outside of them
+    pub active_hours: Option<ActiveHours>,
+    /// Ids `crawl` starts from
+    pub seed_ids: Vec<usize>,
+    /// Ids the scan skips without recording them, such as those crashing the parser
+    pub exclude_ids: Vec<RangeInclusive<usize>>,
+    /// Store how long scraping each torrent took
+    pub record_timings: bool,
+    /// Store comments with the HTML markup they are served with, instead of as plain text
+    pub keep_comment_html: bool,
+    /// Save the stash after this many consecutive scrape errors
+    pub checkpoint_on_error: Option<usize>,
+    /// Print newly stored torrents to stdout as NDJSON while scanning
+    pub emit_ndjson: bool,
+    /// Shell command run for each newly stored torrent, with its JSON on stdin
+    pub on_scrape: Option<String>,
+    /// Only run the `--on-scrape` command for torrents of these categories
+    pub on_scrape_categories: Option<Vec<Category>>,
+    /// Only run the `--on-scrape` command for torrents with at least this many seeders
+    pub on_scrape_min_seeders: Option<usize>,
+    /// Trending page read by `scrape-trending`
+    pub period: TrendingPeriod,
+    /// Log file read by `replay-errors`
+    pub log: Option<PathBuf>,
+    /// Metric `top` ranks torrents by
+    pub by: RankBy,
+    /// Number of torrents `top` prints
+    pub limit: usize,
+    /// Print results as JSON instead of a table
+    pub json: bool,
+    /// How `scrape` and `scrape-url` print the torrent
+    pub format: OutputFormat,
+    /// Text that `find` looks for in torrent names, ignoring case
+    pub name: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            command: Command::default(),
+            metrics_addr: None,
+            only_categories: None,
+            out: None,
+            exclude_nsfw: false,
+            strict: false,
+            category_map: None,
+            resume: false,
+            missing_older_than: None,
+            delay_ms: 50,
+            ignore_robots: false,
+            jitter_ms: 0,
+            seed: None,
+            timeout_secs: 10,
+            retries: 0,
+            max_total_retries: None,
+            min_page_bytes: 1024,
+            max_redirects: 5,
+            base_url: String::from("https://1337x.torrentbay.to"),
+            mirrors: Vec::new(),
+            user_agent: None,
+            cookie: None,
+            http_client: HttpClientKind::default(),
+            proxy: None,
+            comments_path: String::from("/comments.php?torrentid={id}"),
+            files_path: String::from("/files.php?torrentid={id}"),
+            comment_count_selector: String::from(crate::COMMENT_COUNT_SELECTOR),
+            comments_refresh_days: None,
+            keep_raw_comments: false,
+            url: None,
+            missing: Vec::new(),
+            start_id: 100,
+            resume_from_cursor: true,
+            since_id_file: None,
+            end_id: None,
+            samples: 100,
+            count: None,
+            count_skipped: false,
+            min_free_disk: None,
+            min_size: None,
+            max_size: None,
+            min_seeders: None,
+            id: None,
+            torrent_file: None,
+            shared_ratelimit: None,
+            max_rps: 10.0,
+            stash_encoding: StashEncoding::default(),
+            from: None,
+            to: None,
+            input: None,
+            omit_fields: Vec::new(),
+            append_only: false,
+            category_omit_fields: BTreeMap::new(),
+            ids: Vec::new(),
+            bulk: false,
+            bulk_path: String::from("/api/torrents?ids={ids}"),
+            bulk_size: 50,
+            active_hours: None,
+            seed_ids: Vec::new(),
+            exclude_ids: Vec::new(),
+            record_timings: false,
+            keep_comment_html: false,
+            checkpoint_on_error: None,
+            emit_ndjson: false,
+            on_scrape: None,
+            on_scrape_categories: None,
+            on_scrape_min_seeders: None,
+            period: TrendingPeriod::default(),
+            log: None,
+            by: RankBy::default(),
+            limit: 10,
+            json: false,
+            format: OutputFormat::default(),
+            name: None,
+        }
+    }
+}
+
+/// Help printed by `--help`
+pub const USAGE: &str = include_str!("usage.txt");
+
+/// Flags that take no value
+const SWITCHES: &[&str] = &[
+    "--exclude-nsfw", "--strict", "--resume", "--ignore-robots", "--keep-raw-comments", "--resume-from-cursor", "--ignore-cursor",
+    "--count-skipped", "--append-only", "--bulk", "--record-timings", "--keep-comment-html", "--emit-ndjson", "--json",
+];
+
+/// Flags that do something instead of setting an option, which environment variables don't stand for
+const ACTIONS: &[&str] = &["--help"];
+
+/// Error of arguments that aren't flags nor commands
+#[derive(Debug)]
+struct UnknownArgument(String);
+
+impl std::fmt::Display for UnknownArgument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown argument: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownArgument {}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, anyhow::Error> {
+    args.next().ok_or_else(|| anyhow!("Missing value for {flag}"))
+}
+
+/// Parses a human size such as "1.5 GB"
+fn parse_size(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<u64, anyhow::Error> {
+    let value = next_value(args, flag)?;
+    parse_data_size(&value).ok_or_else(|| anyhow!("Invalid size {value:?} for {flag}"))
+}
+
+fn parse_value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, anyhow::Error>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = next_value(args, flag)?;
+    value.parse().map_err(|err| anyhow!("Invalid value {value:?} for {flag}: {err}"))
+}
+
+/// Reads a file of ids to exclude, one id or range like `1000-2000` per line.
+/// Blank lines and lines starting with `#` are ignored.
+fn read_id_ranges(path: &Path) -> Result<Vec<RangeInclusive<usize>>, anyhow::Error> {
+    let data = std::fs::read_to_string(path).map_err(|err| anyhow!("Failed to read {}: {err}", path.display()))?;
+    let mut ranges = Vec::new();
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || anyhow!("Invalid id or range {line:?} at {}:{}", path.display(), i + 1);
+        let range = match line.split_once('-') {
+            Some((start, end)) => start.trim().parse().map_err(|_| invalid())?..=end.trim().parse().map_err(|_| invalid())?,
+            None => {
+                let id = line.parse().map_err(|_| invalid())?;
+                id..=id
+            }
+        };
+        if range.is_empty() {
+            return Err(invalid());
+        }
+        ranges.push(range);
+    }
+    Ok(ranges)
+}
+
+/// Parses a comma-separated list of mirror URLs
+fn parse_mirrors(value: &str) -> Vec<String> {
+    value.split(',').map(|mirror| mirror.trim().trim_end_matches('/')).filter(|mirror| !mirror.is_empty()).map(String::from).collect()
+}
+
+impl Config {
+    /// Reads defaults from `SCRAPER_*` environment variables, for deployments where flags are awkward
+    fn from_env() -> Result<Self, anyhow::Error> {
+        Config::from_vars(std::env::vars_os().filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?))))
+    }
+
+    /// Reads a variable for every flag, named after it like `SCRAPER_BASE_URL` for `--base-url`.
+    /// Switches are set by `1` or `true` and left alone by `0`, `false` or an empty value.
+    /// Variables that don't stand for a flag are ignored with a warning, as other tools may share the prefix.
+    fn from_vars(vars: impl Iterator<Item = (String, String)>) -> Result<Self, anyhow::Error> {
+        let mut config = Config::default();
+        let mut vars = vars.filter(|(name, _)| name.starts_with("SCRAPER_")).collect::<Vec<_>>();
+        vars.sort();
+        for (name, value) in vars {
+            let flag = format!("--{}", name["SCRAPER_".len()..].to_ascii_lowercase().replace('_', "-"));
+            if ACTIONS.contains(&flag.as_str()) {
+                warn!("Ignoring {name}, as {flag} can only be given on the command line");
+                continue;
+            }
+            let result = match SWITCHES.contains(&flag.as_str()) {
+                true => match value.trim().to_ascii_lowercase().as_str() {
+                    "1" | "true" => config.apply_arg(&flag, &mut std::iter::empty()),
+                    "0" | "false" | "" => Ok(()),
+                    _ => Err(anyhow!("expected 1, true, 0 or false, got {value}")),
+                },
+                false => config.apply_arg(&flag, &mut std::iter::once(value)),
+            };
+            match result {
+                Err(err) if err.is::<UnknownArgument>() => warn!("Ignoring {name}, which doesn't stand for any flag"),
+                result => result.map_err(|err| anyhow!("Invalid environment variable {name}: {err}"))?,
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns the mirror to scrape a torrent from, rotating over `mirrors` when there are some
+    pub fn mirror_for(&self, id: usize) -> &str {
+        match self.mirrors.is_empty() {
+            true => &self.base_url,
+            false => &self.mirrors[id % self.mirrors.len()],
+        }
+    }
+
+    /// Tells whether a field is left out of the records of a category
+    pub fn omits(&self, category: &Category, field: OptionalField) -> bool {
+        self.omit_fields.contains(&field) || self.category_omit_fields.get(category).is_some_and(|fields| fields.contains(&field))
+    }
+
+    /// Tells whether `--exclude-ids` lists an id
+    pub fn is_excluded(&self, id: usize) -> bool {
+        self.exclude_ids.iter().any(|range| range.contains(&id))
+    }
+
+    /// Reads the configuration from the environment then the command line, flags taking precedence
+    pub fn from_args() -> Result<Self, anyhow::Error> {
+        let mut config = Config::from_env()?;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            config.apply_arg(&arg, &mut args)?;
+        }
+
+        if config.max_rps <= 0.0 {
+            bail!("--max-rps must be positive");
+        }
+        if config.bulk_size == 0 {
+            bail!("--bulk-size must be positive");
+        }
+
+        Ok(config)
+    }
+
+    /// Applies a command line argument, taking its value from `args` when it has one
+    fn apply_arg(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> Result<(), anyhow::Error> {
+        match arg {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            "--metrics-addr" => self.metrics_addr = Some(next_value(args, arg)?),
+            "--only-categories" => self.only_categories = Some(Category::parse_list(&next_value(args, arg)?)),
+            "--out" => self.out = Some(PathBuf::from(next_value(args, arg)?)),
+            "--exclude-nsfw" => self.exclude_nsfw = true,
+            "--strict" => self.strict = true,
+            "--category-map" => self.category_map = Some(PathBuf::from(next_value(args, arg)?)),
+            "--resume" => self.resume = true,
+            "--missing-older-than" => self.missing_older_than = Some(parse_value(args, arg)?),
+            "--delay-ms" => self.delay_ms = parse_value(args, arg)?,
+            "--ignore-robots" => self.ignore_robots = true,
+            "--jitter-ms" => self.jitter_ms = parse_value(args, arg)?,
+            "--seed" => self.seed = Some(parse_value(args, arg)?),
+            "--timeout-secs" => self.timeout_secs = parse_value(args, arg)?,
+            "--retries" => self.retries = parse_value(args, arg)?,
+            "--max-total-retries" => self.max_total_retries = Some(parse_value(args, arg)?),
+            "--min-page-bytes" => self.min_page_bytes = parse_value(args, arg)?,
+            "--max-redirects" => self.max_redirects = parse_value(args, arg)?,
+            "--base-url" => {
+                self.base_url = next_value(args, arg)?.trim_end_matches('/').to_string();
+                self.mirrors.clear();
+            }
+            "--mirrors" => self.mirrors = parse_mirrors(&next_value(args, arg)?),
+            "--user-agent" => self.user_agent = Some(next_value(args, arg)?),
+            "--cookie" => self.cookie = Some(next_value(args, arg)?),
+            "--http-client" => self.http_client = parse_value(args, arg)?,
+            "--proxy" => {
+                let proxy = next_value(args, arg)?;
+                if !proxy.starts_with("http://") {
+                    bail!("--proxy must be an http:// URL: {proxy}");
+                }
+                self.proxy = Some(proxy);
+            }
+            "--missing" => self.missing = RecordPart::parse_list(&next_value(args, arg)?)?,
+            "--comments-path" => {
+                let comments_path = next_value(args, arg)?;
+                if !comments_path.contains("{id}") {
+                    bail!("--comments-path must contain {{id}}: {comments_path}");
+                }
+                self.comments_path = comments_path;
+            }
+            "--files-path" => {
+                let files_path = next_value(args, arg)?;
+                if !files_path.contains("{id}") {
+                    bail!("--files-path must contain {{id}}: {files_path}");
+                }
+                self.files_path = files_path;
+            }
+            "--comment-count-selector" => {
+                let selector = next_value(args, arg)?;
+                if scraper::Selector::parse(&selector).is_err() {
+                    bail!("Invalid CSS selector for {arg}: {selector}");
+                }
+                self.comment_count_selector = selector;
+            }
+            "--comments-refresh-days" => self.comments_refresh_days = Some(parse_value(args, arg)?),
+            "--keep-raw-comments" => self.keep_raw_comments = true,
+            "--start-id" | "--start" => self.start_id = parse_value(args, arg)?,
+            "--end-id" | "--end" => self.end_id = Some(parse_value(args, arg)?),
+            "--samples" => self.samples = parse_value(args, arg)?,
+            "--resume-from-cursor" => self.resume_from_cursor = true,
+            "--ignore-cursor" => self.resume_from_cursor = false,
+            "--since-id-file" => self.since_id_file = Some(PathBuf::from(next_value(args, arg)?)),
+            "--count" => self.count = Some(parse_value(args, arg)?),
+            "--count-skipped" => self.count_skipped = true,
+            "--min-free-disk" => self.min_free_disk = Some(parse_size(args, arg)?),
+            "--min-size" => self.min_size = Some(parse_size(args, arg)?),
+            "--max-size" => self.max_size = Some(parse_size(args, arg)?),
+            "--min-seeders" => self.min_seeders = Some(parse_value(args, arg)?),
+            "--id" => self.id = Some(parse_value(args, arg)?),
+            "--shared-ratelimit" => self.shared_ratelimit = Some(PathBuf::from(next_value(args, arg)?)),
+            "--max-rps" => self.max_rps = parse_value(args, arg)?,
+            "--from" => self.from = Some(parse_value(args, arg)?),
+            "--to" => self.to = Some(parse_value(args, arg)?),
+            "--in" => self.input = Some(PathBuf::from(next_value(args, arg)?)),
+            "--stash-encoding" => self.stash_encoding = parse_value(args, arg)?,
+            "--omit-fields" => self.omit_fields = OptionalField::parse_list(&next_value(args, arg)?)?,
+            "--append-only" => self.append_only = true,
+            "--omit-fields-for" => {
+                let value = next_value(args, arg)?;
+                let Some((category, fields)) = value.split_once('=') else {
+                    bail!("{arg} expects category=fields, got {value}");
+                };
+                self.category_omit_fields.entry(Category::parse(category)).or_default().extend(OptionalField::parse_list(fields)?);
+            }
+            "--ids" => {
+                let ids = next_value(args, arg)?;
+                self.ids = ids.split(',').map(|id| id.trim().parse()).collect::<Result<_, _>>().map_err(|err| anyhow!("Invalid value {ids:?} for --ids: {err}"))?;
+            }
+            "--bulk" => self.bulk = true,
+            "--bulk-path" => {
+                let bulk_path = next_value(args, arg)?;
+                if !bulk_path.contains("{ids}") {
+                    bail!("--bulk-path must contain {{ids}}: {bulk_path}");
+                }
+                self.bulk_path = bulk_path;
+            }
+            "--bulk-size" => self.bulk_size = parse_value(args, arg)?,
+            "--active-hours" => self.active_hours = Some(parse_value(args, arg)?),
+            "--exclude-ids" => self.exclude_ids = read_id_ranges(Path::new(&next_value(args, arg)?))?,
+            "--seed-ids" => {
+                let seed_ids = next_value(args, arg)?;
+                self.seed_ids = seed_ids.split(',').map(|id| id.trim().parse()).collect::<Result<_, _>>().map_err(|err| anyhow!("Invalid value {seed_ids:?} for --seed-ids: {err}"))?;
+            }
+            "--record-timings" => self.record_timings = true,
+            "--keep-comment-html" => self.keep_comment_html = true,
+            "--checkpoint-on-error" => self.checkpoint_on_error = Some(parse_value(args, arg)?),
+            "--emit-ndjson" => self.emit_ndjson = true,
+            "--on-scrape" => self.on_scrape = Some(next_value(args, arg)?),
+            "--on-scrape-categories" => self.on_scrape_categories = Some(Category::parse_list(&next_value(args, arg)?)),
+            "--on-scrape-min-seeders" => self.on_scrape_min_seeders = Some(parse_value(args, arg)?),
+            "--period" => self.period = parse_value(args, arg)?,
+            "--log" => self.log = Some(PathBuf::from(next_value(args, arg)?)),
+            "--by" => self.by = parse_value(args, arg)?,
+            "--limit" => self.limit = parse_value(args, arg)?,
+            "--json" => self.json = true,
+            "--name" => self.name = Some(next_value(args, arg)?),
+            "--format" => self.format = parse_value(args, arg)?,
+            "scan" => self.command = Command::Scan,
+            "export" => self.command = Command::Export,
+            "prune" => self.command = Command::Prune,
+            "refresh" => self.command = Command::Refresh,
+            "refill" => self.command = Command::Refill,
+            "stats" => self.command = Command::Stats,
+            "selftest" => self.command = Command::Selftest,
+            "top" => self.command = Command::Top,
+            "backfill-magnets" => self.command = Command::BackfillMagnets,
+            "reindex" => self.command = Command::Reindex,
+            "validate-magnets" => self.command = Command::ValidateMagnets,
+            "replay-errors" => self.command = Command::ReplayErrors,
+            "scrape-trending" => self.command = Command::ScrapeTrending,
+            "convert" => self.command = Command::Convert,
+            "compact" => self.command = Command::Compact,
+            "probe" => self.command = Command::Probe,
+            "find" => self.command = Command::Find,
+            "crawl" => self.command = Command::Crawl,
+            "export-infohashes" => self.command = Command::ExportInfohashes,
+            "export-urls" => self.command = Command::ExportUrls,
+            "export-media-map" => self.command = Command::ExportMediaMap,
+            "verify-torrent" => {
+                self.command = Command::VerifyTorrent;
+                self.torrent_file = Some(PathBuf::from(next_value(args, arg)?));
+            }
+            "scrape" => {
+                self.command = Command::Scrape;
+                self.id = Some(parse_value(args, arg)?);
+            }
+            "scrape-url" => {
+                self.command = Command::ScrapeUrl;
+                self.url = Some(next_value(args, arg)?);
+            }
+            _ => return Err(UnknownArgument(arg.to_string()).into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<Config, anyhow::Error> {
+        Config::from_vars(vars.iter().map(|(name, value)| (name.to_string(), value.to_string())))
+    }
+
+    #[test]
+    fn reads_every_flag_from_the_environment() {
+        let config = from_vars(&[
+            ("SCRAPER_MIRRORS", "https://a.example/, https://b.example"),
+            ("SCRAPER_USER_AGENT", "friendly-scraper"),
+            ("SCRAPER_DELAY_MS", "250"),
+            ("SCRAPER_MIN_FREE_DISK", "2 GB"),
+            ("SCRAPER_APPEND_ONLY", "true"),
+            ("SCRAPER_STRICT", "0"),
+            ("PATH", "/usr/bin"),
+        ]).unwrap();
+        assert_eq!(config.mirrors, ["https://a.example", "https://b.example"]);
+        assert_eq!(config.user_agent.as_deref(), Some("friendly-scraper"));
+        assert_eq!(config.delay_ms, 250);
+        assert_eq!(config.min_free_disk, Some(2 << 30));
+        assert!(config.append_only);
+        assert!(!config.strict);
+    }
+
+    #[test]
+    fn rejects_invalid_environment() {
+        let err = from_vars(&[("SCRAPER_DELAY_MS", "soon")]).unwrap_err();
+        assert!(err.to_string().contains("SCRAPER_DELAY_MS"), "{err}");
+        assert!(from_vars(&[("SCRAPER_BULK", "maybe")]).is_err());
+        assert!(from_vars(&[("SCRAPER_PROXY", "socks5://proxy:1080")]).is_err());
+    }
+
+    #[test]
+    fn ignores_variables_that_are_not_options() {
+        let config = from_vars(&[
+            ("SCRAPER_PROXY", "http://proxy:3128"),
+            ("SCRAPER_NOT_A_FLAG", "1"),
+            ("SCRAPER_SCAN", "1"),
+            // Would print the usage and exit if taken for --help
+            ("SCRAPER_HELP", "1"),
+        ]).unwrap();
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy:3128"));
+    }
+
+    #[test]
+    fn active_hours_wrap_around_midnight() {
+        let at = |hour: u64, minute: u64| 1_700_006_400 + hour * 3600 + minute * 60;
+        let night: ActiveHours = " 22:00-06:30 UTC ".parse().unwrap();
+        assert!(night.utc);
+        assert!(night.contains(at(23, 0)));
+        assert!(night.contains(at(6, 29)));
+        assert!(!night.contains(at(6, 30)));
+        assert!(!night.contains(at(12, 0)));
+
+        let day: ActiveHours = "09:00-17:00UTC".parse().unwrap();
+        assert!(day.contains(at(9, 0)));
+        assert!(!day.contains(at(17, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_active_hours() {
+        assert!("09:00-09:00 UTC".parse::<ActiveHours>().is_err());
+        assert!("09:00".parse::<ActiveHours>().is_err());
+        assert!("9h-17h".parse::<ActiveHours>().is_err());
+        assert!(!"01:00-06:00".parse::<ActiveHours>().unwrap().utc);
+    }
+
+    #[test]
+    fn reads_excluded_ids_and_ranges() {
+        let path = std::env::temp_dir().join(format!("x1337x-scraper-exclude-{}", std::process::id()));
+        std::fs::write(&path, "# known crashers\n101\n\n 103-104 \n").unwrap();
+        let mut config = Config::default();
+        config.apply_arg("--exclude-ids", &mut std::iter::once(path.to_string_lossy().into_owned())).unwrap();
+        assert_eq!(config.exclude_ids, [101..=101, 103..=104]);
+        assert!(!config.is_excluded(100));
+        assert!(config.is_excluded(101));
+        assert!(config.is_excluded(104));
+
+        std::fs::write(&path, "104-103\n").unwrap();
+        let err = read_id_ranges(&path).unwrap_err();
+        assert!(err.to_string().contains(":1"), "{err}");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
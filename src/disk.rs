@@ -0,0 +1,56 @@
+use std::path::Path;
+
+/// Source of the space left on the filesystem holding a path, in bytes
+pub trait DiskSpace {
+    fn available(&self, path: &Path) -> Result<u64, anyhow::Error>;
+}
+
+/// Asks the filesystem through `statvfs`
+pub struct Statvfs;
+
+impl DiskSpace for Statvfs {
+    #[cfg(unix)]
+    // Field types vary across platforms
+    #[allow(clippy::unnecessary_cast)]
+    fn available(&self, path: &Path) -> Result<u64, anyhow::Error> {
+        use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: the path is NUL-terminated and statvfs fills `stat` when it succeeds
+        if unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+            let err = std::io::Error::last_os_error();
+            anyhow::bail!("statvfs failed on {}: {err}", path.display());
+        }
+        // SAFETY: statvfs succeeded
+        let stat = unsafe { stat.assume_init() };
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+
+    #[cfg(not(unix))]
+    fn available(&self, _path: &Path) -> Result<u64, anyhow::Error> {
+        anyhow::bail!("Checking free disk space is only supported on unix")
+    }
+}
+
+/// A disk with a set amount of free space, for tests
+#[cfg(test)]
+pub struct FixedDisk(pub u64);
+
+#[cfg(test)]
+impl DiskSpace for FixedDisk {
+    fn available(&self, _path: &Path) -> Result<u64, anyhow::Error> {
+        Ok(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statvfs_reports_the_current_filesystem() {
+        assert!(Statvfs.available(Path::new(".")).unwrap() > 0);
+        assert!(Statvfs.available(Path::new("/nonexistent/stash")).is_err());
+    }
+}
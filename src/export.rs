@@ -0,0 +1,512 @@
+use std::{cmp::Reverse, collections::BinaryHeap, fs::{File, OpenOptions}, io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+use log::*;
+use serde::{Serialize, Deserialize};
+use crate::{canonical_torrent_url, category::CategoryMap, config::Config, infohash::InfoHash, stash::Stash, TorrentInfo};
+
+/// Infohash digests `export_infohashes` holds in memory, 20 bytes each, before sorting them into a temporary file
+const INFOHASH_RUN_LEN: usize = 1 << 20;
+
+/// A stashed torrent along with its id, as written in exports
+#[derive(Serialize)]
+struct Record<'a> {
+    id: usize,
+    #[serde(flatten)]
+    torrent: &'a TorrentInfo,
+    /// Computed for records that don't store it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    magnet_link: Option<String>,
+    subcategory: String,
+}
+
+impl<'a> Record<'a> {
+    fn new(id: usize, torrent: &'a TorrentInfo) -> Self {
+        Record {
+            id,
+            torrent,
+            magnet_link: torrent.magnet_link.is_none().then(|| torrent.magnet_link()),
+            subcategory: torrent.subcategory().to_string(),
+        }
+    }
+}
+
+/// Writes a torrent as an NDJSON line, in the same format as exports
+pub fn write_record(mut out: impl Write, id: usize, torrent: &TorrentInfo) -> Result<(), anyhow::Error> {
+    serde_json::to_writer(&mut out, &Record::new(id, torrent))?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Tracks the progress of an export so that it can be resumed
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Source chunks that have been completely written
+    chunks: Vec<usize>,
+    /// Length of the output after the last completed chunk
+    bytes_written: u64,
+}
+
+impl Manifest {
+    fn path(out: &Path) -> PathBuf {
+        let mut path = out.as_os_str().to_owned();
+        path.push(".manifest.json");
+        PathBuf::from(path)
+    }
+
+    fn load(out: &Path) -> Result<Option<Self>, anyhow::Error> {
+        match std::fs::read_to_string(Self::path(out)) {
+            Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Writes the manifest atomically so that a crash never leaves it half-written
+    fn save(&self, out: &Path) -> Result<(), anyhow::Error> {
+        let path = Self::path(out);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Exports all stashed torrents as NDJSON, one chunk at a time.
+/// Only one chunk is held in memory and records are written as they are read, so memory stays bounded whatever the stash size.
+///
+/// When `--resume` is set and a manifest exists, the output is truncated back to the end of the last completed chunk and the export continues from there.
+/// Categories are renamed according to `category_map`, leaving the stash untouched.
+pub fn export(config: &Config, out: &Path, category_map: &CategoryMap) -> Result<(), anyhow::Error> {
+    let mut manifest = match config.resume {
+        true => Manifest::load(out)?.unwrap_or_default(),
+        false => Manifest::default(),
+    };
+    if !manifest.chunks.is_empty() {
+        info!("Resuming export after {} chunks", manifest.chunks.len());
+    }
+
+    let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(out)?;
+    file.set_len(manifest.bytes_written)?;
+    file.seek(SeekFrom::End(0))?;
+    manifest.save(out)?;
+    let mut writer = BufWriter::new(file);
+
+    let mut exported = 0;
+    for chunk_id in Stash::chunk_ids()? {
+        if manifest.chunks.contains(&chunk_id) {
+            continue;
+        }
+
+        for (id, torrent) in Stash::read_chunk(chunk_id)? {
+            let Some(mut torrent) = torrent.filter(|torrent| !(config.exclude_nsfw && torrent.is_adult())) else {
+                continue;
+            };
+            // The subcategory is interpreted in the context of the site category, so before remapping it
+            let subcategory = torrent.subcategory().to_string();
+            if let Some(category) = category_map.get(&torrent.normalized_category()) {
+                torrent.category = category.clone();
+            }
+            serde_json::to_writer(&mut writer, &Record { subcategory, ..Record::new(id, &torrent) })?;
+            writer.write_all(b"\n")?;
+            exported += 1;
+        }
+
+        writer.flush()?;
+        writer.get_ref().sync_data()?;
+        manifest.chunks.push(chunk_id);
+        manifest.bytes_written = writer.get_mut().stream_position()?;
+        manifest.save(out)?;
+        debug!("Exported chunk {chunk_id}");
+    }
+
+    info!("Exported {exported} torrents to {}", out.display());
+    Ok(())
+}
+
+/// Writes the lowercase infohash of every stashed torrent, once each and sorted, skipping invalid ones.
+/// Chunks are read one at a time and duplicates are dropped by an external sort, so memory stays bounded by `INFOHASH_RUN_LEN` digests whatever the stash size.
+pub fn export_infohashes(config: &Config, out: impl Write) -> Result<(), anyhow::Error> {
+    let mut runs = SortedRuns::new(INFOHASH_RUN_LEN);
+    let mut invalid = 0;
+
+    for chunk_id in Stash::chunk_ids()? {
+        for torrent in Stash::read_chunk(chunk_id)?.into_values().flatten() {
+            if config.only_categories.as_ref().is_some_and(|only| !only.contains(&torrent.normalized_category())) {
+                continue;
+            }
+            if config.min_seeders.is_some_and(|min_seeders| torrent.seeders < min_seeders) {
+                continue;
+            }
+            if config.exclude_nsfw && torrent.is_adult() {
+                continue;
+            }
+
+            let Some(infohash) = torrent.infohash.normalized() else {
+                invalid += 1;
+                continue;
+            };
+            runs.push(infohash.to_bytes())?;
+        }
+    }
+    let exported = runs.write_unique(out)?;
+
+    info!("Exported {exported} infohashes ({invalid} invalid ones skipped)");
+    Ok(())
+}
+
+/// Sorts digests in runs of bounded length, spilling each full run to a temporary file, then merges them without duplicates
+struct SortedRuns {
+    run: Vec<[u8; 20]>,
+    run_len: usize,
+    dir: PathBuf,
+    runs: Vec<PathBuf>,
+}
+
+impl SortedRuns {
+    fn new(run_len: usize) -> SortedRuns {
+        static RUNS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = RUNS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("x1337x-scraper-runs-{}-{n}", std::process::id()));
+        SortedRuns { run: Vec::new(), run_len, dir, runs: Vec::new() }
+    }
+
+    fn push(&mut self, digest: [u8; 20]) -> Result<(), anyhow::Error> {
+        self.run.push(digest);
+        if self.run.len() >= self.run_len {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the current run to a temporary file, sorted and without duplicates
+    fn spill(&mut self) -> Result<(), anyhow::Error> {
+        self.run.sort_unstable();
+        self.run.dedup();
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(self.runs.len().to_string());
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for digest in self.run.drain(..) {
+            writer.write_all(&digest)?;
+        }
+        writer.flush()?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// Writes every digest once as lowercase hex, in ascending order, returning how many were written
+    fn write_unique(mut self, out: impl Write) -> Result<usize, anyhow::Error> {
+        let mut writer = BufWriter::new(out);
+        let mut written = 0;
+        let mut write = |digest: &[u8; 20]| -> Result<(), std::io::Error> {
+            written += 1;
+            digest.iter().try_for_each(|byte| write!(writer, "{byte:02x}"))?;
+            writeln!(writer)
+        };
+
+        if self.runs.is_empty() {
+            self.run.sort_unstable();
+            self.run.dedup();
+            self.run.iter().try_for_each(&mut write)?;
+        } else {
+            if !self.run.is_empty() {
+                self.spill()?;
+            }
+            let mut readers = self.runs.iter().map(|path| Ok(BufReader::new(File::open(path)?))).collect::<Result<Vec<_>, std::io::Error>>()?;
+            let mut heap = BinaryHeap::new();
+            for (i, reader) in readers.iter_mut().enumerate() {
+                if let Some(digest) = read_digest(reader)? {
+                    heap.push(Reverse((digest, i)));
+                }
+            }
+            let mut last = None;
+            while let Some(Reverse((digest, i))) = heap.pop() {
+                if last != Some(digest) {
+                    write(&digest)?;
+                    last = Some(digest);
+                }
+                if let Some(next) = read_digest(&mut readers[i])? {
+                    heap.push(Reverse((next, i)));
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+}
+
+impl Drop for SortedRuns {
+    fn drop(&mut self) {
+        if !self.runs.is_empty() {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+fn read_digest(reader: &mut impl Read) -> Result<Option<[u8; 20]>, std::io::Error> {
+    let mut digest = [0; 20];
+    match reader.read_exact(&mut digest) {
+        Ok(()) => Ok(Some(digest)),
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes the canonical URL of every stashed torrent, one per line, for other indexers to crawl
+pub fn export_urls(config: &Config, out: impl Write) -> Result<(), anyhow::Error> {
+    let mut writer = BufWriter::new(out);
+    let mut exported = 0;
+
+    for chunk_id in Stash::chunk_ids()? {
+        for (id, torrent) in Stash::read_chunk(chunk_id)? {
+            let Some(torrent) = torrent.filter(|torrent| !(config.exclude_nsfw && torrent.is_adult())) else {
+                continue;
+            };
+            writeln!(writer, "{}", canonical_torrent_url(&config.base_url, id, &torrent.name))?;
+            exported += 1;
+        }
+    }
+    writer.flush()?;
+
+    info!("Exported {exported} torrent URLs");
+    Ok(())
+}
+
+/// Links a torrent to the movie or series it holds, as written by `export_media_map`
+#[derive(Serialize)]
+struct MediaEntry<'a> {
+    infohash: InfoHash,
+    tmdb_id: Option<usize>,
+    series_id: Option<&'a str>,
+    name: &'a str,
+}
+
+/// Writes the infohash of every stashed torrent linked to a movie or series along with its media ids, as NDJSON.
+/// Torrents without media ids or with an invalid infohash are skipped.
+pub fn export_media_map(config: &Config, out: impl Write) -> Result<(), anyhow::Error> {
+    let mut writer = BufWriter::new(out);
+    let (mut exported, mut invalid) = (0, 0);
+
+    for chunk_id in Stash::chunk_ids()? {
+        for torrent in Stash::read_chunk(chunk_id)?.into_values().flatten() {
+            if torrent.tmdb_id.is_none() && torrent.series_id.is_none() {
+                continue;
+            }
+            if config.exclude_nsfw && torrent.is_adult() {
+                continue;
+            }
+            let Some(infohash) = torrent.infohash.normalized() else {
+                invalid += 1;
+                continue;
+            };
+            let entry = MediaEntry {
+                infohash,
+                tmdb_id: torrent.tmdb_id,
+                series_id: torrent.series_id.as_deref(),
+                name: &torrent.name,
+            };
+            serde_json::to_writer(&mut writer, &entry)?;
+            writer.write_all(b"\n")?;
+            exported += 1;
+        }
+    }
+    writer.flush()?;
+
+    info!("Exported {exported} media ids ({invalid} torrents with an invalid infohash skipped)");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(n: u32) -> [u8; 20] {
+        let mut digest = [0; 20];
+        digest[16..].copy_from_slice(&n.to_be_bytes());
+        digest
+    }
+
+    fn write_unique(runs: SortedRuns) -> Vec<String> {
+        let mut out = Vec::new();
+        runs.write_unique(&mut out).unwrap();
+        String::from_utf8(out).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn dedupes_many_chunks_in_bounded_runs() {
+        let mut runs = SortedRuns::new(64);
+        // 500 chunks of 40 ids each, every infohash showing up in two chunks far apart
+        for chunk_id in 0..500 {
+            for i in 0..40 {
+                runs.push(digest((chunk_id % 250) * 40 + i)).unwrap();
+                assert!(runs.run.len() < 64);
+            }
+        }
+        assert_eq!(runs.runs.len(), 20000 / 64);
+        let dir = runs.dir.clone();
+
+        let lines = write_unique(runs);
+        assert_eq!(lines.len(), 10000);
+        assert!(lines.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(lines[1], "0000000000000000000000000000000000000001");
+        assert!(!dir.exists());
+    }
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn torrent(name: &str) -> TorrentInfo {
+        let mut torrent = crate::parse_torrent_html(include_str!("../test.html"), NOW, crate::COMMENT_COUNT_SELECTOR).unwrap().unwrap();
+        torrent.name = String::from(name);
+        torrent
+    }
+
+    /// Stashes a torrent in each of three chunks
+    fn stash_torrents() {
+        let mut stash = Stash::open().unwrap();
+        for id in [10, 1010, 2010] {
+            stash.insert(id, Some(torrent(&format!("torrent {id}"))), NOW).unwrap();
+        }
+        stash.save().unwrap();
+    }
+
+    #[test]
+    fn writes_one_json_record_per_line() {
+        let mut out = Vec::new();
+        write_record(&mut out, 10, &torrent("first")).unwrap();
+        write_record(&mut out, 11, &torrent("second")).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with('\n'));
+        let records = out.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!((&records[0]["id"], &records[0]["name"]), (&serde_json::json!(10), &serde_json::json!("first")));
+        assert_eq!((&records[1]["id"], &records[1]["name"]), (&serde_json::json!(11), &serde_json::json!("second")));
+        assert!(records[0]["magnet_link"].as_str().unwrap().starts_with("magnet:?xt=urn:btih:"));
+    }
+
+    #[test]
+    fn resumes_a_killed_export() {
+        let stash = crate::stash::TempStash::new("export-resume");
+        stash_torrents();
+        let (complete, out) = (stash.path().join("complete.ndjson"), stash.path().join("out.ndjson"));
+        export(&Config::default(), &complete, &CategoryMap::default()).unwrap();
+        let expected = std::fs::read_to_string(&complete).unwrap();
+        assert_eq!(expected.lines().count(), 3);
+
+        // Killed while writing the second chunk, after the manifest recorded the first one
+        let first_line = expected.split_inclusive('\n').next().unwrap();
+        std::fs::write(&out, format!("{first_line}{{\"id\":1010,\"na")).unwrap();
+        Manifest { chunks: vec![0], bytes_written: first_line.len() as u64 }.save(&out).unwrap();
+
+        export(&Config { resume: true, ..Config::default() }, &out, &CategoryMap::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), expected);
+        assert_eq!(Manifest::load(&out).unwrap().unwrap().chunks, [0, 1, 2]);
+    }
+
+    #[test]
+    fn exports_the_infohashes_of_two_torrents() {
+        let _stash = crate::stash::TempStash::new("export-infohashes");
+        let mut stash = Stash::open().unwrap();
+        for (id, infohash) in [(1, "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"), (2, "4738fb09f6ae3e2f45bca0ffec9f32779500ab03"), (1001, "ffffffffffffffffffffffffffffffffffffffff"), (1002, "not a hash")] {
+            let mut torrent = torrent(&id.to_string());
+            torrent.infohash = InfoHash::raw(infohash.to_string());
+            stash.insert(id, Some(torrent), NOW).unwrap();
+        }
+        stash.insert(3, None, NOW).unwrap();
+        stash.save().unwrap();
+
+        let mut out = Vec::new();
+        export_infohashes(&Config::default(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "4738fb09f6ae3e2f45bca0ffec9f32779500ab03\nffffffffffffffffffffffffffffffffffffffff\n");
+    }
+
+    #[test]
+    fn exports_the_urls_of_two_torrents() {
+        let _stash = crate::stash::TempStash::new("export-urls");
+        let mut stash = Stash::open().unwrap();
+        stash.insert(5558130, Some(torrent("The Weapon (2023) [1080p]")), NOW).unwrap();
+        stash.insert(12, Some(torrent("Ubuntu 24.04 LTS")), NOW).unwrap();
+        stash.insert(13, None, NOW).unwrap();
+        stash.save().unwrap();
+
+        let mut out = Vec::new();
+        export_urls(&Config { base_url: String::from("https://1337x.example"), ..Config::default() }, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "https://1337x.example/torrent/12/Ubuntu-24-04-LTS/\nhttps://1337x.example/torrent/5558130/The-Weapon-2023-1080p/\n",
+        );
+    }
+
+    #[test]
+    fn exports_only_torrents_with_media_ids() {
+        let _stash = crate::stash::TempStash::new("export-media-map");
+        let mut stash = Stash::open().unwrap();
+        let mut movie = torrent("movie");
+        movie.tmdb_id = Some(603);
+        let mut episode = torrent("episode");
+        episode.tmdb_id = None;
+        episode.series_id = Some(String::from("tt0903747"));
+        // The fixture page links to a movie
+        let mut plain = torrent("plain");
+        plain.tmdb_id = None;
+        stash.insert(1, Some(movie), NOW).unwrap();
+        stash.insert(2, Some(plain), NOW).unwrap();
+        stash.insert(1001, Some(episode), NOW).unwrap();
+        stash.save().unwrap();
+
+        let mut out = Vec::new();
+        export_media_map(&Config::default(), &mut out).unwrap();
+        let records = String::from_utf8(out).unwrap().lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+        assert_eq!(records, [
+            serde_json::json!({"infohash": "4738fb09f6ae3e2f45bca0ffec9f32779500ab03", "tmdb_id": 603, "series_id": null, "name": "movie"}),
+            serde_json::json!({"infohash": "4738fb09f6ae3e2f45bca0ffec9f32779500ab03", "tmdb_id": null, "series_id": "tt0903747", "name": "episode"}),
+        ]);
+    }
+
+    #[test]
+    fn exports_renamed_categories() {
+        let stash = crate::stash::TempStash::new("export-category-map");
+        let mut db = Stash::open().unwrap();
+        let mut tv = torrent("tv");
+        tv.category = String::from("TV");
+        db.insert(1, Some(torrent("movie")), NOW).unwrap();
+        db.insert(2, Some(tv), NOW).unwrap();
+        db.save().unwrap();
+
+        let map_path = stash.path().join("map.json");
+        std::fs::write(&map_path, r#"{"movies": "film"}"#).unwrap();
+        let out = stash.path().join("out.ndjson");
+        export(&Config::default(), &out, &crate::category::load_map(&map_path).unwrap()).unwrap();
+        let records = std::fs::read_to_string(&out).unwrap().lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+        assert_eq!((&records[0]["category"], &records[0]["subcategory"]), (&serde_json::json!("film"), &serde_json::json!("HEVC/x265")));
+        assert_eq!(records[1]["category"], "TV");
+        assert_eq!(Stash::read_torrent(1).unwrap().unwrap().category, "Movies");
+    }
+
+    #[test]
+    fn exports_leave_out_adult_torrents_on_demand() {
+        let stash = crate::stash::TempStash::new("export-nsfw");
+        let mut db = Stash::open().unwrap();
+        let mut xxx = torrent("xxx");
+        xxx.category = String::from("XXX");
+        db.insert(1, Some(torrent("movie")), NOW).unwrap();
+        db.insert(2, Some(xxx), NOW).unwrap();
+        db.save().unwrap();
+
+        let out = stash.path().join("out.ndjson");
+        export(&Config::default(), &out, &CategoryMap::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap().lines().count(), 2);
+        export(&Config { exclude_nsfw: true, ..Config::default() }, &out, &CategoryMap::default()).unwrap();
+        let records = std::fs::read_to_string(&out).unwrap().lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["name"], "movie");
+    }
+
+    #[test]
+    fn dedupes_in_memory_below_one_run() {
+        let mut runs = SortedRuns::new(64);
+        for n in [3, 1, 3, 2, 1] {
+            runs.push(digest(n)).unwrap();
+        }
+        assert!(runs.runs.is_empty());
+        let lines = write_unique(runs);
+        assert_eq!(lines, ["0000000000000000000000000000000000000001", "0000000000000000000000000000000000000002", "0000000000000000000000000000000000000003"]);
+    }
+}
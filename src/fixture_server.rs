@@ -0,0 +1,130 @@
+use std::{collections::BTreeMap, io::{Read, Write}, net::{SocketAddr, TcpListener, TcpStream}, sync::{Arc, Mutex}};
+
+/// A canned response of the fixture server
+#[derive(Clone)]
+pub struct Route {
+    status_code: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Route {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Route {
+        Route::status(200, body)
+    }
+
+    pub fn status(status_code: u16, body: impl Into<Vec<u8>>) -> Route {
+        Route { status_code, headers: Vec::new(), body: body.into() }
+    }
+
+    /// Adds a header, which can be given several times like `Set-Cookie`
+    pub fn header(mut self, name: &str, value: &str) -> Route {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// A request received by the fixture server
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// Path and query of the request
+    pub path: String,
+    /// Headers, with lowercase names
+    pub headers: Vec<(String, String)>,
+    /// Index of the connection the request came on, counting from 0 in the order connections were accepted
+    pub connection: usize,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serves canned responses over HTTP on a background thread, the way `metrics::serve` does, so that tests go through the real client.
+/// Paths without a route get a 404. Connections are kept alive unless the client or the route sends `Connection: close`.
+pub struct FixtureServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl FixtureServer {
+    pub fn start(routes: impl IntoIterator<Item = (&'static str, Route)>) -> FixtureServer {
+        let routes = routes.into_iter().map(|(path, route)| (path.to_string(), route)).collect::<BTreeMap<_, _>>();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            let routes = Arc::new(routes);
+            for (connection, stream) in listener.incoming().enumerate() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let (routes, received) = (Arc::clone(&routes), Arc::clone(&received));
+                std::thread::spawn(move || {
+                    while let Some(request) = read_request(&mut stream, connection) {
+                        let route = routes.get(&request.path).cloned().unwrap_or_else(|| Route::status(404, "Not Found"));
+                        let close = [request.header("connection"), route.header_value("connection")].into_iter().flatten().any(|value| value.eq_ignore_ascii_case("close"));
+                        received.lock().unwrap().push(request);
+                        if write_response(&mut stream, &route, close).is_err() || close {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        FixtureServer { addr, requests }
+    }
+
+    /// Base URL of the server, without trailing slash
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Returns the requests received so far, in order
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &mut TcpStream, connection: usize) -> Option<Request> {
+    let mut data = Vec::new();
+    let mut buffer = [0; 1024];
+    while !data.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).ok()?;
+        if read == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buffer[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&data).into_owned();
+    let mut lines = head.lines();
+    let path = lines.next()?.split(' ').nth(1)?.to_string();
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    Some(Request { path, headers, connection })
+}
+
+fn write_response(stream: &mut TcpStream, route: &Route, close: bool) -> std::io::Result<()> {
+    let mut response = format!("HTTP/1.1 {} Fixture\r\nContent-Length: {}\r\n", route.status_code, route.body.len());
+    if route.header_value("connection").is_none() {
+        response.push_str(if close { "Connection: close\r\n" } else { "Connection: keep-alive\r\n" });
+    }
+    for (name, value) in &route.headers {
+        response.push_str(&format!("{name}: {value}\r\n"));
+    }
+    response.push_str("\r\n");
+    stream.write_all(response.as_bytes())?;
+    stream.write_all(&route.body)
+}
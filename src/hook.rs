@@ -0,0 +1,81 @@
+use std::{io::Write, process::{Command, Stdio}, sync::mpsc::{sync_channel, SyncSender, TrySendError}, thread::JoinHandle};
+use anyhow::bail;
+use log::*;
+
+/// How many torrents can wait for the hook before new ones are dropped
+const QUEUE_SIZE: usize = 64;
+
+/// Runs a shell command for each scraped torrent, with its JSON on stdin and its id in `TORRENT_ID`.
+///
+/// Commands run one at a time on a background thread so that a slow hook never holds back the scrape.
+pub struct ScrapeHook {
+    sender: Option<SyncSender<(usize, String)>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ScrapeHook {
+    pub fn spawn(command: String) -> Self {
+        let (sender, receiver) = sync_channel::<(usize, String)>(QUEUE_SIZE);
+        let worker = std::thread::spawn(move || {
+            for (id, json) in receiver {
+                if let Err(err) = run(&command, id, &json) {
+                    warn!("Scrape hook failed for torrent {id}: {err}");
+                }
+            }
+        });
+        ScrapeHook {
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues a torrent for the hook, dropping it if the queue is full
+    pub fn send(&self, id: usize, json: String) {
+        let Some(sender) = &self.sender else { return };
+        match sender.try_send((id, json)) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => warn!("Scrape hook queue is full, skipping torrent {id}"),
+            Err(TrySendError::Disconnected(_)) => error!("Scrape hook worker stopped, skipping torrent {id}"),
+        }
+    }
+}
+
+impl Drop for ScrapeHook {
+    /// Waits for queued torrents to be handled
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run(command: &str, id: usize, json: &str) -> Result<(), anyhow::Error> {
+    let mut child = Command::new("sh").arg("-c").arg(command).env("TORRENT_ID", id.to_string()).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(json.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("Command exited with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_the_command_for_each_torrent() {
+        let out = std::env::temp_dir().join(format!("x1337x-scraper-hook-{}", std::process::id()));
+        let hook = ScrapeHook::spawn(format!("{{ echo \"$TORRENT_ID\"; cat; echo; }} >> {}", out.display()));
+        hook.send(1, String::from("{\"name\":\"a\"}"));
+        hook.send(2, String::from("{\"name\":\"b\"}"));
+        drop(hook);
+
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "1\n{\"name\":\"a\"}\n2\n{\"name\":\"b\"}\n");
+        std::fs::remove_file(out).unwrap();
+        assert!(run("exit 3", 1, "{}").is_err());
+    }
+}
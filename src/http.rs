@@ -0,0 +1,358 @@
+use std::{borrow::Cow, collections::BTreeMap, sync::{atomic::{AtomicU64, Ordering}, Mutex}, time::{Duration, Instant}};
+use log::*;
+use crate::{metrics::METRICS, ratelimit::SharedRateLimiter, split_url};
+
+pub struct Response {
+    pub status_code: i32,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+}
+
+/// Everything the scraper needs from an HTTP client
+pub trait HttpClient {
+    fn get(&self, url: &str) -> Result<Response, anyhow::Error>;
+
+    /// Fetches a page unless it wasn't modified since `ts`, in which case servers honoring the header answer 304
+    fn get_if_modified_since(&self, url: &str, ts: u64) -> Result<Response, anyhow::Error> {
+        let _ = ts;
+        self.get(url)
+    }
+
+    /// Takes a retry from the budget of the run, telling whether there was one left
+    fn take_retry(&self) -> bool {
+        true
+    }
+
+    /// Returns how many retries are left for the run, if they are limited
+    fn remaining_retries(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Delay before a retry, doubling with each attempt up to about half a minute
+pub fn backoff(attempt: usize) -> Duration {
+    Duration::from_millis(500 << attempt.min(6))
+}
+
+/// Formats a timestamp the way `If-Modified-Since` wants it
+pub fn http_date(ts: u64) -> Option<String> {
+    let date = chrono::NaiveDateTime::from_timestamp_opt(ts as i64, 0)?;
+    Some(date.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+}
+
+/// What the clients share: their settings, retry budget, rate limiter and cookies
+pub struct ClientSettings {
+    pub timeout_secs: u64,
+    /// Redirects followed before giving up, such as mirrors sending the slug to its canonical form
+    pub max_redirects: usize,
+    pub user_agent: Option<String>,
+    /// Times a failed request is retried
+    pub retries: usize,
+    /// Retries left for the whole run, unlimited when `None`
+    pub retry_budget: Option<AtomicU64>,
+    pub rate_limiter: Option<SharedRateLimiter>,
+    pub cookies: CookieJar,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        ClientSettings {
+            timeout_secs: 10,
+            max_redirects: 5,
+            user_agent: None,
+            retries: 0,
+            retry_budget: None,
+            rate_limiter: None,
+            cookies: CookieJar::default(),
+        }
+    }
+}
+
+impl ClientSettings {
+    /// Sends a request until it succeeds or runs out of retries, waiting for the rate limiter of its host before each attempt
+    pub fn fetch(&self, url: &str, mut send: impl FnMut() -> Result<Response, anyhow::Error>) -> Result<Response, anyhow::Error> {
+        let mut attempt = 0;
+        loop {
+            if let (Some(rate_limiter), Some((host, _))) = (&self.rate_limiter, split_url(url)) {
+                rate_limiter.wait(host)?;
+            }
+            let result = send();
+            let failed = match &result {
+                Ok(resp) => resp.status_code == 429 || resp.status_code >= 500,
+                Err(_) => true,
+            };
+            if failed && attempt < self.retries && self.take_retry() {
+                attempt += 1;
+                debug!("Retrying {url} (attempt {attempt}/{})", self.retries);
+                std::thread::sleep(backoff(attempt));
+                continue;
+            }
+            return result;
+        }
+    }
+
+    /// Takes a retry from the budget of the run, telling whether there was one left
+    pub fn take_retry(&self) -> bool {
+        let taken = match &self.retry_budget {
+            Some(budget) => {
+                let result = budget.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| remaining.checked_sub(1));
+                METRICS.retries_remaining.store(result.map_or(0, |remaining| remaining - 1), Ordering::Relaxed);
+                result.is_ok()
+            }
+            None => true,
+        };
+        match taken {
+            true => METRICS.retries.fetch_add(1, Ordering::Relaxed),
+            false => METRICS.retries_denied.fetch_add(1, Ordering::Relaxed),
+        };
+        taken
+    }
+
+    /// Returns how many retries are left for the run, if they are limited
+    pub fn remaining_retries(&self) -> Option<u64> {
+        self.retry_budget.as_ref().map(|budget| budget.load(Ordering::Relaxed))
+    }
+}
+
+/// Client backed by minreq, kept as a fallback for `PooledClient`.
+///
+/// minreq opens a new connection, and so does a new TLS handshake, for every request.
+///
+/// minreq follows redirects itself and sends them the headers of the first request, cookies included.
+/// Requests are thus first sent following no redirect, and a redirect is followed by a request without cookies.
+pub struct MinreqClient {
+    pub settings: ClientSettings,
+}
+
+impl MinreqClient {
+    fn request(&self, url: &str, if_modified_since: Option<&str>) -> minreq::Request {
+        let mut request = minreq::get(url).with_timeout(self.settings.timeout_secs).with_max_redirects(self.settings.max_redirects);
+        if let Some(user_agent) = &self.settings.user_agent {
+            request = request.with_header("User-Agent", user_agent);
+        }
+        match if_modified_since {
+            Some(date) => request.with_header("If-Modified-Since", date),
+            None => request,
+        }
+    }
+}
+
+/// Cookies sent with requests, such as the consent cookie some mirrors want before serving pages.
+///
+/// Cookies are only sent to the host that set them, or to the mirrors for those given with `--cookie`.
+/// They aren't scoped to a path, and expire at the end of the run.
+#[derive(Default)]
+pub struct CookieJar {
+    /// Cookies by host, without the port like browsers do
+    cookies: Mutex<BTreeMap<String, BTreeMap<String, String>>>,
+}
+
+impl CookieJar {
+    /// Reads cookies in the format of a `Cookie` header, such as "name=value; name2=value2", to send to the given hosts
+    pub fn parse<'a>(cookies: &str, hosts: impl IntoIterator<Item = &'a str>) -> Result<CookieJar, anyhow::Error> {
+        let mut jar = BTreeMap::new();
+        for cookie in cookies.split(';').map(str::trim).filter(|cookie| !cookie.is_empty()) {
+            let Some((name, value)) = cookie.split_once('=') else {
+                anyhow::bail!("expected name=value, got {cookie}");
+            };
+            jar.insert(name.trim().to_string(), value.trim().to_string());
+        }
+        let cookies = hosts.into_iter().map(|host| (host.to_ascii_lowercase(), jar.clone())).collect();
+        Ok(CookieJar { cookies: Mutex::new(cookies) })
+    }
+
+    pub(crate) fn header(&self, host: &str) -> Option<String> {
+        let cookies = self.cookies.lock().unwrap();
+        let cookies = cookies.get(&host.to_ascii_lowercase()).filter(|cookies| !cookies.is_empty())?;
+        Some(cookies.iter().map(|(name, value)| format!("{name}={value}")).collect::<Vec<_>>().join("; "))
+    }
+
+    /// Stores or removes the cookie of a `Set-Cookie` header sent by a host
+    pub(crate) fn update(&self, host: &str, set_cookie: &str) {
+        let mut attributes = set_cookie.split(';').map(str::trim);
+        let Some((name, value)) = attributes.next().and_then(|cookie| cookie.split_once('=')) else {
+            return;
+        };
+        let (name, value) = (name.trim().to_string(), value.trim().to_string());
+        let expired = attributes.any(|attribute| attribute.eq_ignore_ascii_case("max-age=0"));
+        let mut cookies = self.cookies.lock().unwrap();
+        let cookies = cookies.entry(host.to_ascii_lowercase()).or_default();
+        if expired {
+            debug!("Removing cookie {name} of {host}");
+            cookies.remove(&name);
+        } else if cookies.get(&name) != Some(&value) {
+            debug!("Storing cookie {name} of {host}");
+            cookies.insert(name, value);
+        }
+    }
+}
+
+impl MinreqClient {
+    fn fetch(&self, url: &str, if_modified_since: Option<&str>) -> Result<Response, anyhow::Error> {
+        self.settings.fetch(url, || {
+            let resp = self.send_with_cookies(url, if_modified_since)?;
+            Ok(Response {
+                status_code: resp.status_code,
+                body: resp.into_bytes(),
+            })
+        })
+    }
+
+    /// Sends a request with the cookies of its host, following no redirect so that they never reach another host.
+    /// A redirect is requested again without cookies, leaving minreq to follow it, and the cookies it sets are ignored
+    /// since minreq doesn't tell which host they come from.
+    fn send_with_cookies(&self, url: &str, if_modified_since: Option<&str>) -> Result<minreq::Response, minreq::Error> {
+        let host = split_url(url).map(|(host, _)| host);
+        let mut request = self.request(url, if_modified_since).with_max_redirects(0);
+        if let Some(cookies) = host.and_then(|host| self.settings.cookies.header(host)) {
+            request = request.with_header("Cookie", cookies);
+        }
+        let resp = match send(request) {
+            Err(minreq::Error::TooManyRedirections) if self.settings.max_redirects > 0 => {
+                debug!("Following the redirect of {url} without cookies");
+                return send(self.request(url, if_modified_since));
+            }
+            result => result?,
+        };
+        // minreq keeps a single header per name, so only the last cookie of a response is stored
+        if let (Some(host), Some(set_cookie)) = (host, resp.headers.get("set-cookie")) {
+            self.settings.cookies.update(host, set_cookie);
+        }
+        Ok(resp)
+    }
+}
+
+impl HttpClient for MinreqClient {
+    fn get(&self, url: &str) -> Result<Response, anyhow::Error> {
+        self.fetch(url, None)
+    }
+
+    fn get_if_modified_since(&self, url: &str, ts: u64) -> Result<Response, anyhow::Error> {
+        self.fetch(url, http_date(ts).as_deref())
+    }
+
+    fn take_retry(&self) -> bool {
+        self.settings.take_retry()
+    }
+
+    fn remaining_retries(&self) -> Option<u64> {
+        self.settings.remaining_retries()
+    }
+}
+
+/// Sends a request, recording its duration and outcome in the metrics
+fn send(request: minreq::Request) -> Result<minreq::Response, minreq::Error> {
+    record(|| request.send())
+}
+
+/// Runs a request, recording its duration and outcome in the metrics
+pub(crate) fn record<T, E>(send: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let start = Instant::now();
+    let result = send();
+    METRICS.request_duration.observe(start.elapsed());
+    METRICS.requests.fetch_add(1, Ordering::Relaxed);
+    if result.is_err() {
+        METRICS.request_errors.fetch_add(1, Ordering::Relaxed);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(retry_budget: Option<u64>) -> MinreqClient {
+        MinreqClient {
+            settings: ClientSettings { retries: 3, retry_budget: retry_budget.map(AtomicU64::new), ..ClientSettings::default() },
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_cap() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(6), Duration::from_secs(32));
+        assert_eq!(backoff(60), backoff(6));
+    }
+
+    #[test]
+    fn requests_time_out() {
+        // Accepts connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/torrent/1/x/", listener.local_addr().unwrap());
+        let settings = || ClientSettings { timeout_secs: 1, retries: 0, ..ClientSettings::default() };
+        let clients: [Box<dyn HttpClient>; 2] = [Box::new(MinreqClient { settings: settings() }), Box::new(crate::pool::PooledClient::new(settings()))];
+        for client in clients {
+            let start = std::time::Instant::now();
+            assert!(client.get(&url).is_err());
+            assert!(start.elapsed() < Duration::from_secs(5), "{:?}", start.elapsed());
+        }
+        drop(listener);
+    }
+
+    #[test]
+    fn retries_come_out_of_the_budget() {
+        let limited = client(Some(2));
+        assert!(limited.take_retry());
+        assert!(limited.take_retry());
+        assert!(!limited.take_retry());
+        assert_eq!(limited.remaining_retries(), Some(0));
+
+        let unlimited = client(None);
+        assert!((0..100).all(|_| unlimited.take_retry()));
+        assert_eq!(unlimited.remaining_retries(), None);
+    }
+
+    #[test]
+    fn cookies_stay_with_their_host() {
+        use crate::fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([
+            ("/consent", Route::ok("consent").header("Set-Cookie", "consent=1; Path=/")),
+            ("/page", Route::ok("page")),
+            ("/away", Route::status(302, "").header("Location", "/page")),
+        ]);
+        let other_host = server.url().replace("127.0.0.1", "localhost");
+        let client = MinreqClient {
+            settings: ClientSettings { cookies: CookieJar::parse("session=abc", ["127.0.0.1"]).unwrap(), ..ClientSettings::default() },
+        };
+
+        client.get(&format!("{}/consent", server.url())).unwrap();
+        client.get(&format!("{}/page", server.url())).unwrap();
+        client.get(&format!("{other_host}/page")).unwrap();
+        assert_eq!(client.get(&format!("{}/away", server.url())).unwrap().text(), "page");
+
+        let cookies = server.requests().into_iter().map(|request| (request.path.clone(), request.header("cookie").map(String::from))).collect::<Vec<_>>();
+        assert_eq!(cookies, [
+            (String::from("/consent"), Some(String::from("session=abc"))),
+            (String::from("/page"), Some(String::from("consent=1; session=abc"))),
+            (String::from("/page"), None),
+            (String::from("/away"), Some(String::from("consent=1; session=abc"))),
+            // Redirects are followed without cookies, as they could lead to another host
+            (String::from("/away"), None),
+            (String::from("/page"), None),
+        ]);
+    }
+
+    #[test]
+    fn cookie_jar_parses_and_updates() {
+        let jar = CookieJar::parse(" consent=1; ;session = abc ", ["1337x.to", "X1337X.ws"]).unwrap();
+        assert_eq!(jar.header("1337x.to").as_deref(), Some("consent=1; session=abc"));
+        assert_eq!(jar.header("x1337x.ws").as_deref(), Some("consent=1; session=abc"));
+        assert_eq!(jar.header("tracker.example"), None);
+        assert!(CookieJar::parse("consent", ["1337x.to"]).is_err());
+
+        jar.update("1337x.to", "session=def; Path=/; HttpOnly");
+        jar.update("1337x.to", "consent=; Max-Age=0");
+        jar.update("1337x.to", "malformed");
+        assert_eq!(jar.header("1337x.to").as_deref(), Some("session=def"));
+        assert_eq!(jar.header("x1337x.ws").as_deref(), Some("consent=1; session=abc"));
+        jar.update("1337x.to", "session=x; max-age=0");
+        assert_eq!(jar.header("1337x.to"), None);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{fmt, str::FromStr};
+use anyhow::bail;
+use serde::{Serialize, Deserialize};
+
+/// A v1 infohash, as 40 lowercase hex characters when parsed.
+///
+/// Records scraped before infohashes were validated may hold anything, so deserializing accepts any string.
+/// Use `normalized` to get a valid infohash out of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InfoHash(String);
+
+impl InfoHash {
+    /// Keeps a value that failed to parse, so that the record holding it can still be stored
+    pub fn raw(value: String) -> Self {
+        InfoHash(value)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.0.len() == 40 && self.0.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+
+    /// Decodes a valid infohash into its 20-byte digest
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let mut bytes = [0; 20];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.0.get(i * 2..i * 2 + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()).unwrap_or_default();
+        }
+        bytes
+    }
+
+    /// Returns the parsed form of the infohash, if it's valid
+    pub fn normalized(&self) -> Option<InfoHash> {
+        self.0.parse().ok()
+    }
+}
+
+impl FromStr for InfoHash {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if value.len() != 40 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("Invalid infohash {value:?}");
+        }
+        Ok(InfoHash(value.to_ascii_lowercase()))
+    }
+}
+
+impl fmt::Display for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn parses_and_lowercases() {
+        let parsed: InfoHash = format!(" {} ", HASH.to_ascii_uppercase()).parse().unwrap();
+        assert_eq!(parsed.as_str(), HASH);
+        assert!(parsed.is_valid());
+        assert!("0123".parse::<InfoHash>().is_err());
+        assert!(HASH.replace('0', "g").parse::<InfoHash>().is_err());
+    }
+
+    #[test]
+    fn normalizes_raw_values() {
+        assert_eq!(InfoHash::raw(HASH.to_ascii_uppercase()).normalized().unwrap().as_str(), HASH);
+        assert!(!InfoHash::raw(String::from("not a hash")).is_valid());
+        assert_eq!(InfoHash::raw(String::from("not a hash")).normalized(), None);
+    }
+
+    #[test]
+    fn decodes_bytes() {
+        let bytes = HASH.parse::<InfoHash>().unwrap().to_bytes();
+        assert_eq!(bytes[..4], [0x01, 0x23, 0x45, 0x67]);
+        assert_eq!(bytes[19], 0x67);
+    }
+}
@@ -0,0 +1,3411 @@
+use std::{cmp::Reverse, collections::{BTreeMap, BTreeSet, BinaryHeap, VecDeque}, io::Write, path::Path, sync::atomic::AtomicU64, time::{Instant, Duration}};
+use log::*;
+use anyhow::{anyhow, bail};
+use serde::{Serialize, Deserialize};
+use scraper::{Selector, Html};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+mod bulk;
+mod bytesize;
+mod category;
+mod clock;
+mod config;
+mod disk;
+mod export;
+mod hook;
+mod http;
+mod infohash;
+mod magnet;
+mod metrics;
+mod msgpack;
+mod pool;
+mod ratelimit;
+mod robots;
+mod stash;
+mod torrent_file;
+mod tracker;
+mod warnings;
+
+#[cfg(test)]
+mod fixture_server;
+
+use bulk::BulkScraper;
+use bytesize::ByteSize;
+use category::{Category, Subcategory};
+use clock::{Clock, SystemClock};
+#[cfg(test)]
+use clock::FixedClock;
+use config::{Command, Config, HttpClientKind, OptionalField, OutputFormat, RankBy, RecordPart, TrendingPeriod};
+use disk::{DiskSpace, Statvfs};
+use hook::ScrapeHook;
+use http::{ClientSettings, CookieJar, HttpClient, MinreqClient};
+use infohash::InfoHash;
+use ratelimit::SharedRateLimiter;
+use stash::Stash;
+use tracker::TrackerProtocol;
+use warnings::WARNINGS;
+
+fn is_zero(val: &usize) -> bool {
+    *val == 0
+}
+
+fn is_zero_u64(val: &u64) -> bool {
+    *val == 0
+}
+
+fn is_false(val: &bool) -> bool {
+    !*val
+}
+
+fn is_user(val: &String) -> bool {
+    val == "user"
+}
+
+fn user() -> String {
+    String::from("user")
+}
+
+fn is_thread_user_avatar(val: &String) -> bool {
+    val == "/images/thread-user.jpg"
+}
+
+fn thread_user_avatar() -> String {
+    String::from("/images/thread-user.jpg")
+}
+
+/// Accepts numbers given either as JSON numbers or strings, and anything unexpected as `None`
+fn lenient_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: std::str::FromStr,
+{
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(number) => number.to_string().parse().ok(),
+        serde_json::Value::String(string) => string.trim().replace(',', "").parse().ok(),
+        _ => None,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TorrentInfo {
+    name: String,
+    description: String,
+    infohash: InfoHash,
+    category: String,
+    ty: String,
+    language: String,
+    total_size: ByteSize,
+    uploader: String,
+    /// Absolute URL of the uploader's profile
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uploader_url: Option<String>,
+    downloads: usize,
+    last_checked_ts: u64,
+    /// When seeders and leechers were counted, for pages showing it apart from `last_checked_ts`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peers_checked_ts: Option<u64>,
+    uploaded_ts: u64,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    seeders: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    leechers: usize,
+    scraped_ts: u64,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tmdb_id: Option<usize>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series_id: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    trackers: Vec<String>,
+    /// HTTP seeds listed along trackers, which aren't announce URLs
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    web_seeds: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    files: Vec<File>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<Comment>,
+    /// Magnet link as found on the page
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_magnet: Option<String>,
+    /// Hash of the raw page, used to detect unchanged pages on refresh
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero_u64")]
+    page_hash: u64,
+    /// Data quality issues met while scraping
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+    /// Number of files announced by the page, which `files` may fall short of
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_count: Option<usize>,
+    /// Number of comments announced by the page, which `comments` may fall short of
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    comment_count: usize,
+    /// Set while scraping when the comment count couldn't be found on the page
+    #[serde(skip)]
+    comment_count_unknown: bool,
+    /// Whether fetching paginated comments failed past the first page, `comments` holding the pages before it
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    comments_incomplete: bool,
+    /// Whether the site marked the torrent as verified
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    verified: bool,
+    /// When the refresh command should scrape this torrent again
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero_u64")]
+    next_refresh_ts: u64,
+    /// When `comments` were fetched, which can be earlier than `scraped_ts` when they were reused
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero_u64")]
+    comments_scraped_ts: u64,
+    /// Magnet link computed at scrape time, absent when the infohash is invalid
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    magnet_link: Option<String>,
+    /// Time spent fetching and parsing the page and comments, recorded with `--record-timings`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scrape_duration_ms: Option<u64>,
+    /// Torrents the page links to, such as related ones, followed by `crawl`
+    #[serde(skip)]
+    linked_ids: Vec<usize>,
+}
+
+impl TorrentInfo {
+    fn normalized_category(&self) -> Category {
+        Category::parse(&self.category)
+    }
+
+    /// Tells whether the torrent is adult content, going by its category
+    fn is_adult(&self) -> bool {
+        self.normalized_category().is_adult()
+    }
+
+    fn subcategory(&self) -> Subcategory {
+        Subcategory::parse(&self.normalized_category(), &self.ty)
+    }
+
+    /// Schedules the next refresh based on the torrent's age, as young torrents change much faster than old ones
+    fn schedule_refresh(&mut self) {
+        let age = self.scraped_ts.saturating_sub(self.uploaded_ts);
+        let interval = match age {
+            age if age < 86400 * 7 => 86400,
+            age if age < 86400 * 30 => 86400 * 7,
+            _ => 86400 * 30,
+        };
+        self.next_refresh_ts = self.scraped_ts + interval;
+    }
+
+    fn omit_fields(&mut self, fields: &[OptionalField]) {
+        for field in fields {
+            match field {
+                OptionalField::Description => self.description.clear(),
+                OptionalField::Images => self.images.clear(),
+                OptionalField::Trackers => {
+                    self.trackers.clear();
+                    self.web_seeds.clear();
+                }
+                OptionalField::Files => self.files.clear(),
+                OptionalField::Comments => self.comments.clear(),
+                OptionalField::Warnings => self.warnings.clear(),
+            }
+        }
+    }
+
+    /// Lists what looks wrong with the record, which is a sign of the parser being out of date
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push(String::from("empty name"));
+        }
+        if !self.has_valid_infohash() {
+            problems.push(format!("invalid infohash {:?}", self.infohash.as_str()));
+        }
+        let files_size: ByteSize = self.files.iter().map(|file| file.size).sum();
+        if self.total_size.is_zero() && !files_size.is_zero() {
+            problems.push(format!("zero size while files add up to {files_size}"));
+        } else if self.total_size.is_zero() {
+            problems.push(String::from("zero size"));
+        }
+        problems
+    }
+
+    fn has_valid_infohash(&self) -> bool {
+        self.infohash.is_valid()
+    }
+
+    /// Tells whether a part of the record is empty while the page announced some content for it
+    fn is_missing(&self, part: RecordPart) -> bool {
+        match part {
+            RecordPart::Files => (self.files.is_empty() && !self.total_size.is_zero()) || self.file_count.is_some_and(|file_count| self.files.len() < file_count),
+            RecordPart::Comments => (self.comments.is_empty() && self.comment_count > 0) || self.comments_incomplete,
+        }
+    }
+
+    /// Returns the record as checked again at `now`, the page being unchanged
+    fn unchanged_since(&self, now: u64) -> TorrentInfo {
+        let mut torrent = self.clone();
+        // The "last checked" text being the same, it now refers to a later time
+        torrent.last_checked_ts += now.saturating_sub(self.scraped_ts);
+        torrent.peers_checked_ts = self.peers_checked_ts.map(|ts| ts + now.saturating_sub(self.scraped_ts));
+        torrent.scraped_ts = now;
+        torrent.schedule_refresh();
+        torrent
+    }
+
+    /// Returns the stored magnet link, or computes it for records scraped before magnets were stored
+    fn magnet_link(&self) -> String {
+        match &self.magnet_link {
+            Some(magnet_link) => magnet_link.clone(),
+            None => self.build_magnet_link(),
+        }
+    }
+
+    /// Stores the magnet link if the infohash is valid, telling whether it was missing
+    fn fill_magnet_link(&mut self) -> bool {
+        if self.magnet_link.is_some() || !self.has_valid_infohash() {
+            return false;
+        }
+        self.magnet_link = Some(self.build_magnet_link());
+        true
+    }
+
+    /// Recomputes the fields derived from the scraped data, telling whether any of them changed.
+    /// Magnet links still matching the infohash are kept, as trackers may have been omitted since they were built.
+    fn reindex(&mut self) -> bool {
+        let (infohash, magnet_link, next_refresh_ts) = (self.infohash.clone(), self.magnet_link.clone(), self.next_refresh_ts);
+        let mut kinds_changed = false;
+        for file in &mut self.files {
+            let kind = FileKind::of(&file.name);
+            kinds_changed |= file.kind != kind;
+            file.kind = kind;
+        }
+        if let Some(normalized) = self.infohash.normalized() {
+            self.infohash = normalized;
+        }
+        if !self.magnet_link.as_ref().is_some_and(|magnet_link| magnet::is_valid_for(magnet_link, self.infohash.as_str())) {
+            self.magnet_link = None;
+            self.fill_magnet_link();
+        }
+        self.schedule_refresh();
+        kinds_changed || (infohash, magnet_link, next_refresh_ts) != (self.infohash.clone(), self.magnet_link.clone(), self.next_refresh_ts)
+    }
+
+    /// Returns the magnet link of the page if it matches our infohash, or builds one otherwise
+    fn build_magnet_link(&self) -> String {
+        match &self.page_magnet {
+            Some(page_magnet) if magnet::is_valid_for(page_magnet, self.infohash.as_str()) => page_magnet.clone(),
+            _ => {
+                magnet::build(self.infohash.as_str(), &self.name, &tracker::unique(&self.trackers), &self.web_seeds)
+            }
+        }
+    }
+
+    fn trackers_by_protocol(&self) -> BTreeMap<TrackerProtocol, Vec<&str>> {
+        tracker::group_by_protocol(&self.trackers)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct File {
+    name: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "ByteSize::is_zero")]
+    size: ByteSize,
+    /// Inferred from the name, `Other` for records scraped before files were classified
+    #[serde(default)]
+    #[serde(skip_serializing_if = "FileKind::is_other")]
+    kind: FileKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FileKind {
+    /// The content itself, such as a video, an album track or an installer
+    Main,
+    Sample,
+    Subtitle,
+    Nfo,
+    #[default]
+    Other,
+}
+
+impl FileKind {
+    fn is_other(&self) -> bool {
+        *self == FileKind::Other
+    }
+
+    /// Classifies a file from its name, such as "movie.sample.mkv" or "subs/en.srt"
+    fn of(name: &str) -> FileKind {
+        let name = name.to_ascii_lowercase();
+        if name.split(|c: char| !c.is_ascii_alphanumeric()).any(|word| word == "sample") {
+            return FileKind::Sample;
+        }
+        let extension = name.rsplit_once('.').map(|(_, extension)| extension).unwrap_or_default();
+        match extension {
+            "srt" | "sub" | "idx" | "ass" | "ssa" | "vtt" => FileKind::Subtitle,
+            "nfo" => FileKind::Nfo,
+            "mkv" | "mp4" | "avi" | "m4v" | "mov" | "wmv" | "ts" | "webm" | "flac" | "mp3" | "m4a" | "ogg" | "wav"
+                | "iso" | "exe" | "msi" | "dmg" | "apk" | "zip" | "rar" | "7z" | "pdf" | "epub" | "mobi" => FileKind::Main,
+            _ => FileKind::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RawComment {
+    avatar: String,
+    class: Option<String>,
+    comment: String,
+    commentid: u64,
+    posted: String,
+    username: Option<String>,
+    #[serde(default, alias = "reputation", deserialize_with = "lenient_number")]
+    karma: Option<i64>,
+    #[serde(default, alias = "total_posts", alias = "postcount", deserialize_with = "lenient_number")]
+    posts: Option<u64>,
+    /// Relative time of the last edit, like `posted`
+    #[serde(default)]
+    edited: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Comment {
+    #[serde(default = "thread_user_avatar")]
+    #[serde(skip_serializing_if = "is_thread_user_avatar")]
+    avatar: String,
+    #[serde(default = "user")]
+    #[serde(skip_serializing_if = "is_user")]
+    class: String,
+    comment: String,
+    commentid: u64,
+    posted: u64,
+    username: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    karma: Option<i64>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    posts: Option<u64>,
+    /// When the comment was last edited, if ever
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edited: Option<u64>,
+}
+
+/// How far in the future a relative time may point before it's considered invalid rather than clock skew
+const MAX_FUTURE_SKEW_SECS: f64 = 300.0;
+
+/// Transforms stuff like "1 year ago", "1.5 hours ago" or "1 hour 30 minutes ago" into a timestamp
+fn parse_time_offset(now: u64, value: &str) -> Option<u64> {
+    let value = normalize_spaces(value);
+    let value = value.as_str();
+    if value.is_empty() {
+        return None;
+    }
+    if value == "just now" {
+        return Some(now);
+    }
+
+    let parts = value.split([' ', ','])
+        .filter(|p| !p.is_empty() && *p != "and")
+        .collect::<Vec<_>>();
+    let (ago, amounts) = parts.split_last()?;
+    if *ago != "ago" || amounts.is_empty() || amounts.len() % 2 != 0 {
+        return None;
+    }
+
+    let mut offset = 0.0;
+    for amount in amounts.chunks(2) {
+        let number = match amount[0].parse::<f64>() {
+            Ok(number) if number.is_finite() => number,
+            _ => return None,
+        };
+        let unit_seconds = match amount[1].trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 86400,
+            "week" => 86400 * 7,
+            "month" => 86400 * 30,
+            "year" => 86400 * 365,
+            "decade" => 86400 * 365 * 10,
+            _ => return None,
+        };
+        offset += number * unit_seconds as f64;
+    }
+
+    // The site clock running slightly ahead of ours yields times just after now
+    if offset < 0.0 {
+        if -offset > MAX_FUTURE_SKEW_SECS {
+            return None;
+        }
+        trace!("Clamping {value:?}, {:.0}s in the future, to now", -offset);
+        return Some(now);
+    }
+    now.checked_sub(offset.round() as u64)
+}
+
+/// Turns unicode spaces such as the non-breaking ones the site sometimes uses into single ASCII spaces, and trims the value
+fn normalize_spaces(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Transforms formatted size like "87.8 MB" or "742.2 KB" into bytes
+fn parse_data_size(value: &str) -> Option<u64> {
+    let value = normalize_spaces(value);
+    if value.is_empty() {
+        return None;
+    }
+
+    let parts = value.split(' ').collect::<Vec<_>>();
+    if parts.len() != 2 {
+        return None;
+    }
+
+    let number = match parts[0].replace(',', "").parse::<f64>() {
+        Ok(number) => number,
+        Err(_) => return None,
+    };
+    let unit = parts[1];
+
+    Some(match unit.trim_end_matches('s') {
+        "B" => number as u64,
+        "KB" => (number * 1024.0) as u64,
+        "MB" => (number * 1024.0 * 1024.0) as u64,
+        "GB" => (number * 1024.0 * 1024.0 * 1024.0) as u64,
+        "TB" => (number * 1024.0 * 1024.0 * 1024.0 * 1024.0) as u64,
+        _ => return None,
+    })
+}
+
+/// Language names that flags stand for, as returned by `language_of_code`
+const LANGUAGES: &[&str] = &[
+    "English", "French", "German", "Spanish", "Italian", "Portuguese", "Dutch", "Russian",
+    "Polish", "Turkish", "Swedish", "Japanese", "Korean", "Chinese", "Hindi", "Arabic",
+];
+
+/// Maps a flag's country or language code to a language name
+fn language_of_code(code: &str) -> Option<&'static str> {
+    Some(match code.to_ascii_lowercase().as_str() {
+        "gb" | "uk" | "us" | "en" => "English",
+        "fr" => "French",
+        "de" => "German",
+        "es" | "mx" => "Spanish",
+        "it" => "Italian",
+        "pt" | "br" => "Portuguese",
+        "nl" => "Dutch",
+        "ru" => "Russian",
+        "pl" => "Polish",
+        "tr" => "Turkish",
+        "se" | "sv" => "Swedish",
+        "jp" | "ja" => "Japanese",
+        "kr" | "ko" => "Korean",
+        "cn" | "zh" => "Chinese",
+        "in" | "hi" => "Hindi",
+        "ar" | "sa" => "Arabic",
+        _ => return None,
+    })
+}
+
+/// Reads the language from a flag image, for pages that don't show it as text
+fn flag_language(element: scraper::ElementRef) -> Option<String> {
+    let img_selector = Selector::parse("img").unwrap();
+    let img = element.select(&img_selector).next()?.value();
+
+    // Labels are either a language name or a code, other labels such as "flag" say nothing
+    for label in [img.attr("alt"), img.attr("title")].into_iter().flatten() {
+        let label = label.trim();
+        if let Some(language) = language_of_code(label) {
+            return Some(language.to_string());
+        }
+        if let Some(language) = LANGUAGES.iter().find(|language| language.eq_ignore_ascii_case(label)) {
+            return Some(language.to_string());
+        }
+    }
+
+    // Flag files are named after the code, as in "/images/flags/gb.png"
+    let src = img.attr("src")?;
+    let file_name = src.rsplit('/').next()?;
+    let code = file_name.split('.').next()?;
+    language_of_code(code).map(|language| language.to_string())
+}
+
+/// Transforms a file like "File(2) Name (1.2 GB)" into a File struct
+fn parse_file(value: &str) -> Option<File> {
+    let value = value.trim();
+    if value.is_empty() || !value.ends_with(')') {
+        return None;
+    }
+
+    let index = value.rfind('(')?;
+    let name = value[..index].trim().to_string();
+    let size = ByteSize::from(parse_data_size(&value[index+1..value.len()-1])?);
+
+    let kind = FileKind::of(&name);
+    Some(File { name, size, kind })
+}
+
+/// Parses the file list items matching a selector
+fn parse_files(html: &Html, selector: &Selector, warnings: &mut Vec<String>) -> Vec<File> {
+    let mut files = Vec::new();
+    for li in html.select(selector) {
+        let raw_file = li.text().collect::<Vec<_>>().join("").trim().to_string();
+        match parse_file(&raw_file) {
+            Some(file) => files.push(file),
+            None => record_warning(warnings, format!("Failed to parse file: {raw_file}")),
+        }
+    }
+    files
+}
+
+/// Extracts the text of an element, keeping line breaks, list items and paragraphs apart
+fn structured_text(element: scraper::ElementRef) -> String {
+    fn push_text(element: scraper::ElementRef, out: &mut String) {
+        for child in element.children() {
+            // Line breaks of the markup are just whitespace, only elements break lines
+            if let Some(text) = child.value().as_text() {
+                out.extend(text.chars().map(|c| if c.is_whitespace() { ' ' } else { c }));
+                continue;
+            }
+            let Some(child) = scraper::ElementRef::wrap(child) else {
+                continue;
+            };
+            match child.value().name() {
+                "script" | "style" => (),
+                "br" => out.push('\n'),
+                "li" => {
+                    out.push_str("\n- ");
+                    push_text(child, out);
+                    out.push('\n');
+                }
+                "tr" => {
+                    out.push('\n');
+                    push_text(child, out);
+                    out.push('\n');
+                }
+                "p" | "div" | "ul" | "ol" | "table" | "blockquote" | "pre" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                    out.push_str("\n\n");
+                    push_text(child, out);
+                    out.push_str("\n\n");
+                }
+                _ => push_text(child, out),
+            }
+        }
+    }
+
+    let mut raw = String::new();
+    push_text(element, &mut raw);
+
+    // Collapse whitespace within lines and keep at most one blank line between paragraphs
+    let mut text = String::new();
+    let mut blank = false;
+    for line in raw.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() || line == "-" {
+            blank = !text.is_empty();
+            continue;
+        }
+        if !text.is_empty() {
+            text.push_str(if blank { "\n\n" } else { "\n" });
+        }
+        text.push_str(&line);
+        blank = false;
+    }
+    text
+}
+
+/// Finds the first integer in a text such as "(12)" or "12 Comments"
+fn first_integer(text: &str) -> Option<usize> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let digits = &text[start..];
+    let end = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+    digits[..end].parse().ok()
+}
+
+/// Hashes a page body with 64-bit FNV-1a, which unlike `DefaultHasher` is stable across builds
+fn hash_page(body: &[u8]) -> u64 {
+    body.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Logs a data quality issue and attaches it to the record being scraped
+fn record_warning(warnings: &mut Vec<String>, message: String) {
+    WARNINGS.warn(&message);
+    warnings.push(message);
+}
+
+/// Maximum length of the HTML snippet attached to parse errors
+const SNIPPET_LEN: usize = 300;
+
+/// Selects the first element matching a selector that the page layout requires.
+/// When nothing matches, the error names the selector and shows the HTML of the closest ancestor that still matches, to tell a layout change apart from a different page.
+fn select_required<'a>(document: &'a Html, selector: &str, what: &str) -> Result<scraper::ElementRef<'a>, anyhow::Error> {
+    let parsed = Selector::parse(selector).map_err(|err| anyhow!("Invalid {what} selector {selector:?}: {err:?}"))?;
+    if let Some(element) = document.select(&parsed).next() {
+        return Ok(element);
+    }
+
+    // Drop trailing compound selectors until something matches
+    let mut prefix = selector;
+    let context = loop {
+        match prefix.rfind(['>', ' ']) {
+            Some(i) => prefix = prefix[..i].trim_end_matches(['>', ' ']),
+            None => break None,
+        }
+        let Ok(parsed) = Selector::parse(prefix) else { continue };
+        if let Some(element) = document.select(&parsed).next() {
+            break Some((prefix, element.html()));
+        }
+    };
+    let (context_selector, html) = context.unwrap_or_else(|| ("html", document.root_element().html()));
+    let html = html.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut end = SNIPPET_LEN.min(html.len());
+    while !html.is_char_boundary(end) {
+        end -= 1;
+    }
+    bail!("No {what} found: selector {selector:?} matched nothing, closest match is {context_selector:?}: {}", &html[..end]);
+}
+
+/// Selector of the comment count on torrent pages, unless `--comment-count-selector` says otherwise
+pub const COMMENT_COUNT_SELECTOR: &str = ".torrent-tabs .tab-nav a[href=\"#comments\"]>span";
+
+/// Hosts of the 1337x mirrors we know of
+const KNOWN_HOSTS: &[&str] = &[
+    "1337x.to",
+    "1337x.st",
+    "1337x.is",
+    "1337x.gd",
+    "x1337x.ws",
+    "x1337x.eu",
+    "x1337x.se",
+    "1337x.torrentbay.to",
+];
+
+/// Splits a URL into its host (without the port, nor the brackets of IPv6 addresses) and its path
+fn split_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"))?;
+    let (authority, path) = match rest.find(['/', '?', '#']) {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    let host = match authority.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next(),
+        None => authority.split(':').next(),
+    };
+    let host = host.filter(|host| !host.is_empty())?;
+    Some((host, path))
+}
+
+/// Extracts the id of a torrent from its URL, such as "https://1337x.to/torrent/5558130/Some-Slug/"
+fn torrent_id_from_url(url: &str, base_url: &str) -> Result<usize, anyhow::Error> {
+    let (host, path) = split_url(url).ok_or_else(|| anyhow!("Invalid URL: {url}"))?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    let base_host = split_url(base_url).map(|(host, _)| host);
+    if !KNOWN_HOSTS.contains(&host) && base_host != Some(host) {
+        bail!("Not a known 1337x mirror: {host}");
+    }
+
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let parts = path.split('/').filter(|p| !p.is_empty()).collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["torrent", id, ..] => id.parse().map_err(|_| anyhow!("Invalid torrent id in URL: {url}")),
+        _ => bail!("Not a torrent URL: {url}"),
+    }
+}
+
+/// Slug of torrent URLs. The site ignores it, so any value leads to the torrent page.
+const TORRENT_SLUG: &str = "friendly-scraper";
+
+/// Builds the URL of a torrent's detail page on a mirror
+pub fn torrent_url(base: &str, id: usize) -> String {
+    format!("{base}/torrent/{id}/{TORRENT_SLUG}/")
+}
+
+/// Labels some layouts put in the infohash box, ahead of the hash
+const INFOHASH_LABELS: &[&str] = &["infohash", "info hash", "hash"];
+
+/// Removes a label such as "Infohash:" from the text of the infohash box
+fn strip_infohash_label(text: &str) -> &str {
+    for label in INFOHASH_LABELS {
+        let Some(prefix) = text.get(..label.len()).filter(|prefix| prefix.eq_ignore_ascii_case(label)) else {
+            continue;
+        };
+        if let Some(rest) = text[prefix.len()..].trim_start().strip_prefix(':') {
+            return rest.trim();
+        }
+    }
+    text
+}
+
+/// Builds the URL under which the site lists a torrent, with the slug it derives from the name
+pub fn canonical_torrent_url(base: &str, id: usize, name: &str) -> String {
+    let slug = name.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect::<Vec<_>>().join("-");
+    match slug.is_empty() {
+        true => torrent_url(base, id),
+        false => format!("{base}/torrent/{id}/{slug}/"),
+    }
+}
+
+/// Builds the URL of a page of a torrent's comments, `comments_path` containing an `{id}` placeholder and optionally a `{page}` one
+pub fn comments_url(base: &str, comments_path: &str, id: usize, page: usize) -> String {
+    format!("{base}{}", comments_path.replace("{id}", &id.to_string()).replace("{page}", &page.to_string()))
+}
+
+/// Builds the URL of the file list of a torrent, `files_path` containing an `{id}` placeholder
+fn files_url(base: &str, files_path: &str, id: usize) -> String {
+    format!("{base}{}", files_path.replace("{id}", &id.to_string()))
+}
+
+/// Resolves a link found on a page of a mirror
+fn absolute_url(base: &str, href: &str) -> String {
+    match href.starts_with("http://") || href.starts_with("https://") {
+        true => href.to_string(),
+        false => format!("{base}/{}", href.trim_start_matches('/')),
+    }
+}
+
+/// Builds the URL of the trending page of a period
+fn trending_url(base: &str, period: TrendingPeriod) -> String {
+    match period {
+        TrendingPeriod::Day => format!("{base}/trending"),
+        TrendingPeriod::Week => format!("{base}/trending/w/"),
+        TrendingPeriod::Month => format!("{base}/trending/m/"),
+    }
+}
+
+/// Extracts the ids of the torrents linked from a listing page, in order and once each
+fn parse_listing_ids(body: &str) -> Vec<usize> {
+    linked_torrent_ids(&Html::parse_document(body))
+}
+
+/// Extracts the ids of the torrents a page links to, in order and once each
+fn linked_torrent_ids(document: &Html) -> Vec<usize> {
+    let link_selector = Selector::parse("a[href^=\"/torrent/\"]").unwrap();
+    let mut ids = Vec::new();
+    for link in document.select(&link_selector) {
+        let Some(id) = link.value().attr("href").and_then(|href| href.split('/').nth(2)?.parse().ok()) else {
+            continue;
+        };
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+fn scrape_url(config: &Config, client: &dyn HttpClient, clock: &dyn Clock, url: &str) -> Result<Option<TorrentInfo>, anyhow::Error> {
+    let id = torrent_id_from_url(url, &config.base_url)?;
+    scrape_torrent(config, client, clock, id, None)
+}
+
+/// Writes a single scraped torrent in the format set by `--format`
+fn write_torrent(mut out: impl Write, config: &Config, info: Option<&TorrentInfo>) -> Result<(), anyhow::Error> {
+    match config.format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string(&info)?)?,
+        OutputFormat::Pretty => writeln!(out, "{}", serde_json::to_string_pretty(&info)?)?,
+        OutputFormat::Text => match info {
+            Some(torrent) => {
+                writeln!(out, "Name:     {}", torrent.name)?;
+                writeln!(out, "Size:     {}", torrent.total_size)?;
+                writeln!(out, "Seeders:  {} ({} leechers)", torrent.seeders, torrent.leechers)?;
+                writeln!(out, "Uploader: {}", torrent.uploader)?;
+                let trackers = torrent.trackers_by_protocol().iter()
+                    .map(|(protocol, trackers)| format!("{} {}", trackers.len(), format!("{protocol:?}").to_ascii_lowercase()))
+                    .collect::<Vec<_>>();
+                writeln!(out, "Trackers: {}", trackers.join(", "))?;
+                writeln!(out, "Magnet:   {}", torrent.magnet_link())?;
+            }
+            None => writeln!(out, "No torrent found")?,
+        },
+    }
+    Ok(())
+}
+
+/// Scrapes a torrent. With `--strict`, records that look broken are errors, so that they are logged rather than stored.
+fn scrape_torrent(config: &Config, client: &dyn HttpClient, clock: &dyn Clock, id: usize, previous: Option<&TorrentInfo>) -> Result<Option<TorrentInfo>, anyhow::Error> {
+    let start = Instant::now();
+    let mut info = fetch_torrent(config, client, clock, id, previous)?;
+    if let Some(torrent) = &mut info {
+        torrent.scrape_duration_ms = config.record_timings.then(|| start.elapsed().as_millis() as u64);
+        check_strict(config, torrent)?;
+    }
+    Ok(info)
+}
+
+/// Fails on records that look broken when `--strict` is set
+fn check_strict(config: &Config, torrent: &TorrentInfo) -> Result<(), anyhow::Error> {
+    if !config.strict {
+        return Ok(());
+    }
+    let problems = torrent.problems();
+    if !problems.is_empty() {
+        bail!("Discarding broken record: {}", problems.join(", "));
+    }
+    Ok(())
+}
+
+/// Tells whether a page says its torrent doesn't exist or is hidden
+fn is_missing_torrent_page(body: &str) -> bool {
+    body.contains("Bad Torrent ID.") || body.contains("This torrent is hidden and pending moderation.")
+}
+
+/// Fetches and parses a torrent page along with its comments
+fn fetch_torrent(config: &Config, client: &dyn HttpClient, clock: &dyn Clock, id: usize, previous: Option<&TorrentInfo>) -> Result<Option<TorrentInfo>, anyhow::Error> {
+    // Mirrors shedding load answer with near-empty pages, which are retried on the next mirror
+    let mut attempt = 0;
+    let (base_url, resp) = loop {
+        let base_url = config.mirror_for(id + attempt);
+        let url = torrent_url(base_url, id);
+        let resp = match previous {
+            Some(previous) => client.get_if_modified_since(&url, previous.scraped_ts)?,
+            None => client.get(&url)?,
+        };
+        let len = resp.body.len() as u64;
+        // Pages of missing torrents can be as small as shed ones, but are final
+        if resp.status_code != 200 || len >= config.min_page_bytes || is_missing_torrent_page(&resp.text()) {
+            break (base_url, resp);
+        }
+        if attempt >= config.retries || !client.take_retry() {
+            bail!("Page of {id} is only {len} bytes, the mirror is probably shedding load");
+        }
+        attempt += 1;
+        warn!("Page of {id} is only {len} bytes, retrying (attempt {attempt}/{})", config.retries);
+        std::thread::sleep(http::backoff(attempt));
+    };
+    let now = clock.now();
+
+    // Servers that honor If-Modified-Since spare us the page entirely
+    if let (304, Some(previous)) = (resp.status_code, previous) {
+        debug!("Torrent {id} is not modified");
+        return Ok(Some(previous.unchanged_since(now)));
+    }
+
+    let body = resp.text();
+    if resp.status_code != 200 {
+        bail!("Unexpected status code {}: {} {}", id, resp.status_code, body);
+    }
+
+    // Skip parsing pages that didn't change since the previous scrape
+    let page_hash = hash_page(body.as_bytes());
+    if let Some(previous) = previous.filter(|previous| previous.page_hash == page_hash) {
+        debug!("Torrent {id} is unchanged");
+        return Ok(Some(previous.unchanged_since(now)));
+    }
+
+    let Some(mut torrent) = parse_torrent_html(&body, now, &config.comment_count_selector)? else {
+        return Ok(None);
+    };
+    torrent.page_hash = page_hash;
+    torrent.uploader_url = torrent.uploader_url.map(|href| absolute_url(base_url, &href));
+    torrent.schedule_refresh();
+    torrent.fill_magnet_link();
+    let category = torrent.normalized_category();
+    if let Some(fields) = config.category_omit_fields.get(&category) {
+        torrent.omit_fields(fields);
+    }
+
+    // Large file lists may be left out of the page and loaded separately
+    if torrent.files.is_empty() && torrent.file_count.is_some_and(|file_count| file_count > 0) && !config.omits(&category, OptionalField::Files) {
+        let files_resp = client.get(&files_url(base_url, &config.files_path, id))?;
+        match files_resp.status_code {
+            200 => {
+                let fragment = Html::parse_fragment(&files_resp.text());
+                torrent.files = parse_files(&fragment, &Selector::parse("li").unwrap(), &mut torrent.warnings);
+                debug!("Fetched {} files of {id} separately", torrent.files.len());
+            }
+            status_code => record_warning(&mut torrent.warnings, format!("Unexpected status code for files: {status_code}")),
+        }
+    }
+
+    // Reuse recent comments rather than fetching them again, as they rarely change
+    if let (Some(previous), Some(days)) = (previous, config.comments_refresh_days) {
+        let comments_scraped_ts = match previous.comments_scraped_ts {
+            0 => previous.scraped_ts,
+            ts => ts,
+        };
+        if previous.comment_count == torrent.comment_count && !previous.comments_incomplete && now.saturating_sub(comments_scraped_ts) < days * 86400 {
+            debug!("Reusing the comments of torrent {id}");
+            torrent.comments = previous.comments.clone();
+            torrent.comments_scraped_ts = comments_scraped_ts;
+            return Ok(Some(torrent));
+        }
+    }
+
+    // Scrape comments
+    'comments: {if (torrent.comment_count > 0 || torrent.comment_count_unknown) && !config.omits(&category, OptionalField::Comments) {
+        torrent.comments_scraped_ts = now;
+        let paginated = config.comments_path.contains("{page}");
+        let mut raw_comments = Vec::new();
+        let mut seen_comment_ids = BTreeSet::new();
+        for page in 1.. {
+            let url = comments_url(base_url, &config.comments_path, id, page);
+            let page_comments = match fetch_comments_page(config, client, &url, id, page) {
+                Ok(CommentsPage::Comments(page_comments)) => page_comments,
+                // Past the first page, failures keep the comments fetched so far for a later refill to complete
+                outcome if page > 1 => {
+                    let reason = match outcome {
+                        Ok(CommentsPage::Status(status_code)) => format!("status code {status_code}"),
+                        Ok(CommentsPage::Invalid(err)) => err.to_string(),
+                        Err(err) => err.to_string(),
+                        Ok(CommentsPage::Comments(_)) => unreachable!(),
+                    };
+                    record_warning(&mut torrent.warnings, format!("Comments incomplete: page {page} of {id} failed with {reason}"));
+                    torrent.comments_incomplete = true;
+                    break;
+                }
+                Ok(CommentsPage::Status(status_code)) if torrent.comment_count_unknown => {
+                    debug!("No comments for {id}: status code {status_code}");
+                    break 'comments;
+                }
+                Ok(CommentsPage::Status(status_code)) => {
+                    record_warning(&mut torrent.warnings, format!("Unexpected status code for comments: {status_code}"));
+                    break 'comments;
+                }
+                // Torrents without comments may not get a JSON list
+                Ok(CommentsPage::Invalid(err)) if torrent.comment_count_unknown => {
+                    debug!("No comments for {id}: {err}");
+                    break 'comments;
+                }
+                Ok(CommentsPage::Invalid(err)) => return Err(err.into()),
+                Err(err) => return Err(err),
+            };
+            // Pages can overlap, so comments are kept once each, in the order first seen
+            let fetched = raw_comments.len();
+            let page_len = page_comments.len();
+            raw_comments.extend(page_comments.into_iter().filter(|comment| seen_comment_ids.insert(comment.commentid)));
+            if raw_comments.len() - fetched < page_len {
+                debug!("Dropped {} duplicate comments of {id} on page {page}", page_len - (raw_comments.len() - fetched));
+            }
+            // A page without new comments is the last one, or a repeat of it
+            if !paginated || raw_comments.len() == fetched || (!torrent.comment_count_unknown && raw_comments.len() >= torrent.comment_count) {
+                break;
+            }
+        }
+        if torrent.comment_count_unknown {
+            torrent.comment_count = raw_comments.len();
+        }
+        for raw_comment in raw_comments {
+            let posted = match parse_time_offset(now, &raw_comment.posted) {
+                Some(posted) => posted,
+                None => {
+                    record_warning(&mut torrent.warnings, format!("Failed to parse comment posted time: {}", raw_comment.posted));
+                    continue;
+                }
+            };
+            // An unparsable edit time only loses the edit time, not the comment
+            let edited = raw_comment.edited.as_deref().filter(|edited| !edited.trim().is_empty()).and_then(|edited| {
+                let ts = parse_time_offset(now, edited);
+                if ts.is_none() {
+                    record_warning(&mut torrent.warnings, format!("Failed to parse comment edit time: {edited}"));
+                }
+                ts
+            });
+            let comment = Comment {
+                avatar: raw_comment.avatar,
+                class: raw_comment.class.unwrap_or(String::from("[deleted]")),
+                comment: match config.keep_comment_html {
+                    true => raw_comment.comment,
+                    false => comment_text(&raw_comment.comment),
+                },
+                commentid: raw_comment.commentid,
+                posted,
+                username: raw_comment.username.unwrap_or(String::from("[deleted]")),
+                karma: raw_comment.karma,
+                posts: raw_comment.posts,
+                edited,
+            };
+            torrent.comments.push(comment);
+        }
+        if torrent.comments.is_empty() && torrent.comment_count > 0 {
+            record_warning(&mut torrent.warnings, format!("No comments found for {id}"));
+        }
+    }}
+
+    Ok(Some(torrent))
+}
+
+/// Turns the HTML of a comment, which can hold links, quotes and entities, into plain text
+fn comment_text(comment: &str) -> String {
+    if !comment.contains(['<', '&']) {
+        return comment.to_string();
+    }
+    structured_text(Html::parse_fragment(comment).root_element())
+}
+
+/// Outcome of fetching a page of comments
+enum CommentsPage {
+    Comments(Vec<RawComment>),
+    Status(i32),
+    Invalid(serde_json::Error),
+}
+
+fn fetch_comments_page(config: &Config, client: &dyn HttpClient, url: &str, id: usize, page: usize) -> Result<CommentsPage, anyhow::Error> {
+    let resp = client.get(url)?;
+    if resp.status_code != 200 {
+        return Ok(CommentsPage::Status(resp.status_code));
+    }
+    let body = resp.text();
+    if config.keep_raw_comments {
+        if let Err(err) = Stash::write_raw_comments(id, page, &body) {
+            warn!("Failed to keep raw comments of {id}: {err}");
+        }
+    }
+    Ok(match serde_json::from_str(&body) {
+        Ok(raw_comments) => CommentsPage::Comments(raw_comments),
+        Err(err) => CommentsPage::Invalid(err),
+    })
+}
+
+/// Returns the text of the heading right before the list holding an item, such as "Web seeds:"
+fn section_heading(li: scraper::ElementRef) -> String {
+    let Some(list) = li.parent() else {
+        return String::new();
+    };
+    list.prev_siblings()
+        .filter_map(scraper::ElementRef::wrap)
+        .find(|sibling| matches!(sibling.value().name(), "h2" | "h3" | "h4"))
+        .map(|heading| heading.text().collect::<String>())
+        .unwrap_or_default()
+}
+
+/// Error of pages saying that a new torrent is still being processed, which is worth trying again later
+#[derive(Debug)]
+struct BeingProcessed;
+
+impl std::fmt::Display for BeingProcessed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The torrent is being processed, try again later")
+    }
+}
+
+impl std::error::Error for BeingProcessed {}
+
+/// Parses a torrent page, leaving comments to be fetched separately.
+/// Pages of torrents still being processed fail with `BeingProcessed`.
+pub fn parse_torrent_html(body: &str, now: u64, comment_count_selector: &str) -> Result<Option<TorrentInfo>, anyhow::Error> {
+    let document = Html::parse_document(body);
+    let mut warnings = Vec::new();
+
+    // Scrape general information
+    let list_selector = Selector::parse(".list").unwrap();
+    let span_selector = Selector::parse("span").unwrap();
+    let lists = document.select(&list_selector).collect::<Vec<_>>();
+    if lists.len() != 3 {
+        if is_missing_torrent_page(body) {
+            return Ok(None);
+        }
+        if body.to_ascii_lowercase().contains("being processed") {
+            return Err(BeingProcessed.into());
+        }
+        debug!("{body}");
+        bail!("Unexpected number of lists: selector \".list\" matched {} elements instead of 3", lists.len());
+    }
+    let li_selector = Selector::parse("li").unwrap();
+    let strong_selector = Selector::parse("strong").unwrap();
+    // Some pages add a "Seeders/Leechers as of" item, which isn't part of the regular stats
+    let is_peers_as_of = |li: &scraper::ElementRef| li.select(&strong_selector).any(|strong| strong.text().collect::<String>().to_ascii_lowercase().contains("as of"));
+    let mut spans = lists[1].select(&span_selector).collect::<Vec<_>>();
+    spans.extend(lists[2].select(&li_selector).filter(|li| !is_peers_as_of(li)).flat_map(|li| li.select(&span_selector)));
+    // Dead torrents may lack the seeders and leechers block
+    if spans.len() != 10 && spans.len() != 8 {
+        bail!("Unexpected number of spans: {}", spans.len());
+    }
+    let mut category = spans[0].text().next().unwrap_or_default().to_string();
+    let mut ty = spans[1].text().next().unwrap_or_default().to_string();
+
+    // Breadcrumbs (Home > Movies > HD) are more reliable than the info spans, so they win when both disagree
+    let breadcrumb_selector = Selector::parse(".breadcrumb a").unwrap();
+    let breadcrumbs = document.select(&breadcrumb_selector)
+        .map(|a| a.text().collect::<String>().trim().to_string())
+        .filter(|crumb| !crumb.is_empty() && !crumb.eq_ignore_ascii_case("home"))
+        .collect::<Vec<_>>();
+    if let Some(breadcrumb_category) = breadcrumbs.first() {
+        if Category::parse(breadcrumb_category) != Category::parse(&category) {
+            record_warning(&mut warnings, format!("Category mismatch: breadcrumbs say {breadcrumb_category:?}, page says {category:?}"));
+            category = breadcrumb_category.clone();
+        }
+    }
+    if let Some(breadcrumb_ty) = breadcrumbs.get(1) {
+        if !breadcrumb_ty.eq_ignore_ascii_case(ty.trim()) {
+            record_warning(&mut warnings, format!("Type mismatch: breadcrumbs say {breadcrumb_ty:?}, page says {ty:?}"));
+            ty = breadcrumb_ty.clone();
+        }
+    }
+    let language = spans[2].text().map(|t| t.trim()).find(|t| !t.is_empty()).map(|t| t.to_string())
+        .or_else(|| flag_language(spans[2]))
+        .unwrap_or_default();
+    let total_size = spans[3].text().next().unwrap_or_default().to_string();
+    let total_size = parse_data_size(&total_size).map(ByteSize::from).ok_or_else(|| anyhow!("Invalid size: {}", total_size))?;
+    let uploader = spans[4].text().map(|s| s.trim()).find(|s| !s.is_empty()).unwrap_or_default().to_string();
+    let uploader_link_selector = Selector::parse("a[href]").unwrap();
+    let uploader_url = spans[4].select(&uploader_link_selector).next()
+        .and_then(|a| a.value().attr("href"))
+        .map(|href| href.to_string());
+    let downloads = spans[5].text().next().unwrap_or_default().to_string();
+    let downloads = downloads.parse().map_err(|_| anyhow!("Invalid downloads: {}", downloads))?;
+    let last_checked = spans[6].text().next().unwrap_or_default();
+    let last_checked_ts = parse_time_offset(now, last_checked).ok_or_else(|| anyhow!("Invalid last checked: {last_checked:?}"))?;
+    let uploaded = spans[7].text().next().unwrap_or_default();
+    let uploaded_ts = parse_time_offset(now, uploaded).ok_or_else(|| anyhow!("Invalid uploaded: {uploaded:?}"))?;
+    // The peer count time is either its own item or a note next to the counts, such as "(as of 5 minutes ago)"
+    let peers_as_of = lists[2].select(&li_selector).find_map(|li| {
+        let text = li.text().collect::<String>();
+        let index = text.to_ascii_lowercase().find("as of")?;
+        Some(text[index + "as of".len()..].trim().trim_matches(['(', ')', ':']).trim().to_string())
+    });
+    let peers_checked_ts = peers_as_of.and_then(|peers_as_of| {
+        let ts = parse_time_offset(now, &peers_as_of);
+        if ts.is_none() {
+            record_warning(&mut warnings, format!("Invalid peers checked time: {peers_as_of:?}"));
+        }
+        ts
+    });
+    let (seeders, leechers): (usize, usize) = match spans.get(8..10) {
+        Some([seeders, leechers]) => (
+            seeders.text().next().unwrap_or_default().to_string().parse()?,
+            leechers.text().next().unwrap_or_default().to_string().parse()?,
+        ),
+        _ => (0, 0),
+    };
+
+    // Scrape TMDB id
+    let movie_link_selector = Selector::parse(".torrent-detail-info h3>a").unwrap();
+    let movie_link = document.select(&movie_link_selector).next().and_then(|link| {
+        link.value().attr("href").map(|href| href.to_string())
+    });
+    let mut tmdb_id = None;
+    let mut series_id = None;
+    #[allow(clippy::unnecessary_operation)]
+    'tmdb_id: {if let Some(movie_link) = movie_link {
+        let parts = movie_link.split('/').filter(|p| !p.is_empty()).collect::<Vec<_>>();
+        
+        if movie_link.starts_with("/movie/") {
+            if parts.len() != 3 {
+                record_warning(&mut warnings, format!("Unexpected movie link: {movie_link}"));
+                break 'tmdb_id;
+            }
+
+            match parts[1].parse::<usize>() {
+                Ok(id) => tmdb_id = Some(id),
+                Err(err) => {
+                    record_warning(&mut warnings, format!("Unexpected movie link: {movie_link} ({err})"));
+                    break 'tmdb_id;
+                }
+            }
+        } else if movie_link.starts_with("/series/") {
+            if parts.len() != 2 {
+                record_warning(&mut warnings, format!("Unexpected series link: {movie_link}"));
+                break 'tmdb_id;
+            }
+
+            series_id = Some(parts[1].to_string());
+        } else {
+            record_warning(&mut warnings, format!("Unexpected movie link: {movie_link}"));
+        }
+    }};
+
+    // Scrape infohash
+    let infohash_el = select_required(&document, ".infohash-box>p>span", "infohash")?;
+    let infohash = strip_infohash_label(infohash_el.text().collect::<Vec<_>>().join("").trim()).to_string();
+    let infohash = match infohash.parse::<InfoHash>() {
+        Ok(infohash) => infohash,
+        Err(err) => {
+            record_warning(&mut warnings, err.to_string());
+            InfoHash::raw(infohash)
+        }
+    };
+
+    // Scrape the verified badge shown next to the title
+    let verified_selector = Selector::parse(".box-info-heading .verified, .box-info-heading .flaticon-verified, .box-info-heading [title=\"Verified\"]").unwrap();
+    let verified = document.select(&verified_selector).next().is_some();
+
+    // Scrape magnet link
+    let magnet_selector = Selector::parse("a[href^=\"magnet:\"]").unwrap();
+    let page_magnet = document.select(&magnet_selector).next().and_then(|a| {
+        a.value().attr("href").map(|href| href.to_string())
+    });
+
+    // Scrape name and description
+    let h1 = select_required(&document, "h1", "h1")?;
+    // Only direct text nodes hold the title, nested elements are badges and icons
+    let mut name = h1.children()
+        .filter_map(|node| node.value().as_text())
+        .map(|text| text.trim())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if name.is_empty() {
+        name = h1.text().collect::<Vec<_>>().join("").trim().to_string();
+    }
+    let mut name_incomplete = false;
+    if name.ends_with("...") {
+        name.pop();
+        name.pop();
+        name.pop();
+        name_incomplete = true;
+    }
+    let description_el = select_required(&document, ".torrent-tabs #description", "description")?;
+    let mut description = structured_text(description_el);
+    if description == "No description given." {
+        description.clear();
+    }
+    // Descriptions often open with a line repeating the name, possibly followed by more text, which is all the name when it was truncated
+    let first_line = description.lines().next().unwrap_or_default();
+    if !name.is_empty() && first_line.starts_with(&name) {
+        if name_incomplete {
+            name = first_line.to_string();
+        }
+        description = description.lines().skip(1).collect::<Vec<_>>().join("\n").trim_start().to_string();
+    }
+
+    // Scrape images
+    let image_selector = Selector::parse(".torrent-tabs #description img").unwrap();
+    let images = document.select(&image_selector).filter_map(|img| {
+        let img = img.value();
+        if let Some(src) = img.attr("data-original") {
+            return Some(src.to_string());
+        }
+
+        // Images that aren't lazy-loaded, skipping inline data and tracking pixels
+        let src = img.attr("src")?.trim();
+        let is_pixel = img.attr("width") == Some("1") && img.attr("height") == Some("1");
+        let is_placeholder = src.ends_with("/images/profile-load.svg") || src.contains("1x1") || src.contains("spacer.gif");
+        if src.is_empty() || src.starts_with("data:") || is_pixel || is_placeholder {
+            return None;
+        }
+        Some(src.to_string())
+    }).collect::<Vec<_>>();
+
+    // Scrape trackers
+    let tracker_selector = Selector::parse(".torrent-tabs #tracker-list li").unwrap();
+    let (web_seeds, trackers): (Vec<_>, Vec<_>) = document.select(&tracker_selector)
+        .map(|li| (li.text().collect::<Vec<_>>().join("").trim().to_string(), section_heading(li)))
+        .partition(|(url, section)| tracker::is_web_seed(url, section));
+    let trackers = trackers.into_iter().map(|(url, _)| url).collect::<Vec<_>>();
+    let web_seeds = web_seeds.into_iter().map(|(url, _)| url).collect::<Vec<_>>();
+
+    // Scrape files
+    let file_selector = Selector::parse(".torrent-tabs #files li").unwrap();
+    let files = parse_files(&document, &file_selector, &mut warnings);
+
+    // Scrape the file count from labels like "Files: 12", which the list may fall short of
+    let file_count_selector = Selector::parse(".torrent-tabs a[href=\"#files\"], .torrent-tabs #files>h2, .torrent-tabs #files>h3, .list li").unwrap();
+    let file_count = document.select(&file_count_selector)
+        .map(|label| label.text().collect::<String>())
+        .filter(|label| label.trim_start().to_ascii_lowercase().starts_with("files"))
+        .find_map(|label| first_integer(&label));
+
+    // Scrape comment count
+    let comment_count_selector = Selector::parse(comment_count_selector).map_err(|err| anyhow!("Invalid comment count selector: {err:?}"))?;
+    let comment_count = document.select(&comment_count_selector).next()
+        .and_then(|span| first_integer(&span.text().collect::<String>()));
+    if comment_count.is_none() {
+        debug!("No comment count found, comments will be fetched anyway");
+    }
+
+    let torrent = TorrentInfo {
+        name,
+        description,
+        ty,
+        category,
+        images,
+        trackers,
+        web_seeds,
+        files,
+        comments: Vec::new(),
+        infohash,
+        language,
+        total_size,
+        uploader,
+        uploader_url,
+        downloads,
+        last_checked_ts,
+        peers_checked_ts,
+        uploaded_ts,
+        seeders,
+        leechers,
+        scraped_ts: now,
+        tmdb_id,
+        series_id,
+        page_magnet,
+        page_hash: 0,
+        warnings,
+        file_count,
+        comment_count: comment_count.unwrap_or_default(),
+        comment_count_unknown: comment_count.is_none(),
+        comments_incomplete: false,
+        verified,
+        next_refresh_ts: 0,
+        comments_scraped_ts: 0,
+        magnet_link: None,
+        scrape_duration_ms: None,
+        linked_ids: linked_torrent_ids(&document),
+    };
+
+    Ok(Some(torrent))
+}
+
+/// Runs the scraper with the options of the command line and the environment
+pub fn run() {
+    env_logger::init();
+
+    let mut config = match Config::from_args() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(metrics_addr) = &config.metrics_addr {
+        if let Err(err) = metrics::serve(metrics_addr) {
+            error!("Failed to serve metrics on {metrics_addr}: {err}");
+            std::process::exit(1);
+        }
+    }
+
+    let rate_limiter = match &config.shared_ratelimit {
+        Some(dir) => match SharedRateLimiter::new(dir.clone(), config.max_rps) {
+            Ok(rate_limiter) => Some(rate_limiter),
+            Err(err) => {
+                error!("Failed to set up the shared rate limiter: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let cookies = match &config.cookie {
+        Some(cookie) => match CookieJar::parse(cookie, std::iter::once(&config.base_url).chain(&config.mirrors).filter_map(|mirror| Some(split_url(mirror)?.0))) {
+            Ok(cookies) => cookies,
+            Err(err) => {
+                error!("Invalid --cookie: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => CookieJar::default(),
+    };
+    let settings = ClientSettings {
+        timeout_secs: config.timeout_secs,
+        max_redirects: config.max_redirects,
+        user_agent: config.user_agent.clone(),
+        retries: config.retries,
+        retry_budget: config.max_total_retries.map(AtomicU64::new),
+        rate_limiter,
+        cookies,
+    };
+    let client: Box<dyn HttpClient> = match (config.http_client, &config.proxy) {
+        (HttpClientKind::Pooled, None) => Box::new(pool::PooledClient::new(settings)),
+        (HttpClientKind::Pooled, Some(proxy)) => match pool::PooledClient::new(settings).with_proxy(proxy) {
+            Ok(client) => Box::new(client),
+            Err(err) => {
+                error!("Invalid --proxy: {err}");
+                std::process::exit(1);
+            }
+        },
+        (HttpClientKind::Minreq, None) => Box::new(MinreqClient { settings }),
+        (HttpClientKind::Minreq, Some(_)) => {
+            error!("--proxy needs the pooled client, minreq can't go through a proxy");
+            std::process::exit(1);
+        }
+    };
+    if let Some(max_total_retries) = config.max_total_retries {
+        metrics::METRICS.retries_remaining.store(max_total_retries, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // Be as polite as the mirrors ask, only ever slowing down
+    if config.command.crawls() && !config.ignore_robots {
+        let mirrors = match config.mirrors.is_empty() {
+            true => vec![config.base_url.clone()],
+            false => config.mirrors.clone(),
+        };
+        let crawl_delay = mirrors.iter().filter_map(|mirror| robots::fetch_crawl_delay(client.as_ref(), mirror, config.user_agent.as_deref())).max();
+        if let Some(crawl_delay) = crawl_delay.filter(|crawl_delay| crawl_delay.as_millis() as u64 > config.delay_ms) {
+            info!("Raising the delay between torrents to the crawl delay of {}ms", crawl_delay.as_millis());
+            config.delay_ms = crawl_delay.as_millis() as u64;
+        }
+    }
+
+    Stash::set_encoding(config.stash_encoding);
+
+    let result = match config.command {
+        Command::Scan => scan(&config, client.as_ref(), &SystemClock, &Statvfs),
+        Command::Export => match &config.out {
+            Some(out) => match &config.category_map {
+                Some(path) => category::load_map(path).and_then(|category_map| export::export(&config, out, &category_map)),
+                None => export::export(&config, out, &Default::default()),
+            },
+            None => Err(anyhow!("export requires --out")),
+        },
+        Command::Prune => prune(&config, &SystemClock),
+        Command::Refresh => refresh(&config, client.as_ref(), &SystemClock),
+        Command::Convert => convert(&config),
+        Command::Compact => Stash::compact().map(|rewritten| info!("Rewrote {rewritten} chunks")),
+        Command::Probe => probe(&config, client.as_ref(), &SystemClock),
+        Command::ScrapeTrending => scrape_trending(&config, client.as_ref(), &SystemClock),
+        Command::ReplayErrors => replay_errors(&config, client.as_ref(), &SystemClock),
+        Command::Refill => refill(&config, client.as_ref(), &SystemClock),
+        Command::Stats => stats(),
+        Command::BackfillMagnets => backfill_magnets(),
+        Command::Reindex => reindex(),
+        Command::ValidateMagnets => validate_magnets(),
+        Command::Top => top(&config),
+        Command::Crawl => crawl(&config, client.as_ref(), &SystemClock),
+        Command::Find => find(&config, std::io::stdout().lock()),
+        Command::VerifyTorrent => verify_torrent(&config),
+        Command::Selftest => selftest(&config, client.as_ref(), &SystemClock),
+        Command::ExportInfohashes => match &config.out {
+            Some(out) => std::fs::File::create(out).map_err(Into::into).and_then(|file| export::export_infohashes(&config, file)),
+            None => export::export_infohashes(&config, std::io::stdout().lock()),
+        },
+        Command::ExportMediaMap => match &config.out {
+            Some(out) => std::fs::File::create(out).map_err(Into::into).and_then(|file| export::export_media_map(&config, file)),
+            None => export::export_media_map(&config, std::io::stdout().lock()),
+        },
+        Command::ExportUrls => match &config.out {
+            Some(out) => std::fs::File::create(out).map_err(Into::into).and_then(|file| export::export_urls(&config, file)),
+            None => export::export_urls(&config, std::io::stdout().lock()),
+        },
+        Command::ScrapeUrl => match &config.url {
+            Some(url) => scrape_url(&config, client.as_ref(), &SystemClock, url).and_then(|info| write_torrent(std::io::stdout().lock(), &config, info.as_ref())),
+            None => Err(anyhow!("scrape-url requires a URL")),
+        },
+        Command::Scrape => match config.id {
+            Some(id) => scrape_torrent(&config, client.as_ref(), &SystemClock, id, None).and_then(|info| write_torrent(std::io::stdout().lock(), &config, info.as_ref())),
+            None => Err(anyhow!("scrape requires an id")),
+        },
+    };
+    WARNINGS.flush();
+    if let Some(remaining) = client.remaining_retries() {
+        info!("{remaining} retries left in the budget");
+    }
+    if let Err(err) = result {
+        error!("{err}");
+        std::process::exit(1);
+    }
+}
+
+/// Tells why a scraped torrent shouldn't be stored, if it shouldn't
+fn rejection_reason(config: &Config, torrent: &TorrentInfo) -> Option<String> {
+    let category = torrent.normalized_category();
+    if config.only_categories.as_ref().is_some_and(|only| !only.contains(&category)) {
+        return Some(format!("category {category} is not wanted"));
+    }
+    if config.exclude_nsfw && category.is_adult() {
+        return Some(String::from("adult content is excluded"));
+    }
+    if config.min_size.is_some_and(|min_size| torrent.total_size.as_u64() < min_size) {
+        return Some(format!("size {} is below the minimum", torrent.total_size));
+    }
+    if config.max_size.is_some_and(|max_size| torrent.total_size.as_u64() > max_size) {
+        return Some(format!("size {} is above the maximum", torrent.total_size));
+    }
+    None
+}
+
+/// Tells whether a stored torrent passes the `--on-scrape` filters
+fn hook_wants(config: &Config, torrent: &TorrentInfo) -> bool {
+    config.on_scrape_categories.as_ref().is_none_or(|only| only.contains(&torrent.normalized_category()))
+        && config.on_scrape_min_seeders.is_none_or(|min_seeders| torrent.seeders >= min_seeders)
+}
+
+fn delay_rng(config: &Config) -> StdRng {
+    match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Picks the delay before the next request, uniformly in `[delay, delay + jitter]`
+fn inter_request_delay(config: &Config, rng: &mut impl Rng) -> Duration {
+    Duration::from_millis(config.delay_ms + rng.gen_range(0..=config.jitter_ms))
+}
+
+/// Torrents known to be live, used by `selftest` when no ids are given
+const SELFTEST_IDS: &[usize] = &[5558130];
+
+fn selftest(config: &Config, client: &dyn HttpClient, clock: &dyn Clock) -> Result<(), anyhow::Error> {
+    let ids = match config.ids.is_empty() {
+        true => SELFTEST_IDS,
+        false => config.ids.as_slice(),
+    };
+
+    let mut failures = 0;
+    for id in ids {
+        let problems = match scrape_torrent(config, client, clock, *id, None) {
+            Ok(Some(torrent)) => torrent.problems(),
+            Ok(None) => vec![String::from("torrent not found")],
+            Err(err) => vec![err.to_string()],
+        };
+        if problems.is_empty() {
+            info!("Torrent {id} parsed fine");
+        } else {
+            error!("Torrent {id} looks broken: {}", problems.join(", "));
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        bail!("{failures} of {} torrents failed, the site markup probably changed", ids.len());
+    }
+    Ok(())
+}
+
+/// Writes stashed torrents whose name contains `--name`, as they are read one chunk at a time
+fn find(config: &Config, mut out: impl Write) -> Result<(), anyhow::Error> {
+    let name = config.name.as_ref().ok_or_else(|| anyhow!("find requires --name"))?.to_lowercase();
+    let mut found = 0;
+    for chunk_id in Stash::chunk_ids()? {
+        for (id, torrent) in Stash::read_chunk(chunk_id)? {
+            let Some(torrent) = torrent else { continue };
+            if !torrent.name.to_lowercase().contains(&name) {
+                continue;
+            }
+            if config.only_categories.as_ref().is_some_and(|only| !only.contains(&torrent.normalized_category())) {
+                continue;
+            }
+            match config.json {
+                true => export::write_record(&mut out, id, &torrent)?,
+                false => writeln!(out, "{id:>9}  {:<13}  {:>9}  {}", torrent.category, torrent.total_size, torrent.name)?,
+            }
+            found += 1;
+        }
+    }
+    info!("Found {found} torrents");
+    Ok(())
+}
+
+/// Outcome of probing a sample of ids
+struct ProbeCounts {
+    range_len: usize,
+    probed: usize,
+    errors: usize,
+    live: usize,
+}
+
+/// Scrapes a random sample of the ids from `--start` to `--end`, counting those holding a torrent
+fn probe_sample(config: &Config, client: &dyn HttpClient, clock: &dyn Clock) -> Result<ProbeCounts, anyhow::Error> {
+    let end_id = config.end_id.ok_or_else(|| anyhow!("probe requires --end"))?;
+    if end_id < config.start_id {
+        bail!("probe requires --end to be at least --start");
+    }
+    let range_len = end_id - config.start_id + 1;
+    let samples = config.samples.min(range_len);
+    let mut rng = delay_rng(config);
+    let mut ids = rand::seq::index::sample(&mut rng, range_len, samples).into_iter().map(|i| config.start_id + i).collect::<Vec<_>>();
+    ids.sort_unstable();
+
+    let (mut live, mut errors) = (0, 0);
+    for (i, id) in ids.iter().enumerate() {
+        if i > 0 {
+            std::thread::sleep(inter_request_delay(config, &mut rng));
+        }
+        let url = torrent_url(config.mirror_for(*id), *id);
+        let result = client.get(&url).and_then(|resp| match resp.status_code {
+            200 => parse_torrent_html(&resp.text(), clock.now(), &config.comment_count_selector),
+            404 => Ok(None),
+            status_code => Err(anyhow!("Unexpected status code {status_code}")),
+        });
+        match result {
+            Ok(Some(_)) => live += 1,
+            Ok(None) => (),
+            Err(err) => {
+                warn!("Failed to probe torrent {id}: {err}");
+                errors += 1;
+            }
+        }
+    }
+
+    let probed = samples - errors;
+    if probed == 0 {
+        bail!("All {samples} probes failed");
+    }
+    Ok(ProbeCounts { range_len, probed, errors, live })
+}
+
+/// Scrapes random ids of a range, without their comments, to estimate how many live torrents it holds
+fn probe(config: &Config, client: &dyn HttpClient, clock: &dyn Clock) -> Result<(), anyhow::Error> {
+    let ProbeCounts { range_len, probed, errors, live } = probe_sample(config, client, clock)?;
+    let density = live as f64 / probed as f64;
+    println!("Probed {probed} of {range_len} ids ({errors} errors)");
+    println!("Live: {live} ({:.1}%)", density * 100.0);
+    println!("Estimated live torrents in {}-{}: {:.0}", config.start_id, config.start_id + range_len - 1, density * range_len as f64);
+    Ok(())
+}
+
+/// Checks that a downloaded .torrent file matches the scraped infohash, to catch tampering mirrors
+fn verify_torrent(config: &Config) -> Result<(), anyhow::Error> {
+    let path = config.torrent_file.as_ref().ok_or_else(|| anyhow!("verify-torrent requires a file"))?;
+    let id = config.id.ok_or_else(|| anyhow!("verify-torrent requires --id"))?;
+    let torrent = Stash::read_torrent(id)?.ok_or_else(|| anyhow!("Torrent {id} is not in the stash"))?;
+
+    let infohash = torrent_file::infohash(&std::fs::read(path)?)?;
+    if torrent.infohash.normalized().as_ref() != Some(&infohash) {
+        warn!("Infohash mismatch for torrent {id}: file has {infohash} but page has {}", torrent.infohash);
+        bail!("{} doesn't match torrent {id}", path.display());
+    }
+
+    info!("{} matches torrent {id}", path.display());
+    Ok(())
+}
+
+fn convert(config: &Config) -> Result<(), anyhow::Error> {
+    let input = config.input.as_deref().ok_or_else(|| anyhow!("convert requires --in"))?;
+    let output = config.out.as_deref().ok_or_else(|| anyhow!("convert requires --out"))?;
+    let to = config.to.ok_or_else(|| anyhow!("convert requires --to"))?;
+    if input == output {
+        bail!("convert can't write into the stash it reads");
+    }
+
+    let records = stash::convert(input, output, config.from, to)?;
+    info!("Converted {records} records from {} to {}", input.display(), output.display());
+    Ok(())
+}
+
+/// Stores magnet links in records scraped before they were, skipping those with an invalid infohash
+fn backfill_magnets() -> Result<(), anyhow::Error> {
+    let (mut filled, mut skipped) = (0, 0);
+    for chunk_id in Stash::chunk_ids()? {
+        let (mut chunk, missing_checks) = Stash::read_merged(chunk_id)?;
+        let mut chunk_filled = 0;
+        for torrent in chunk.values_mut().flatten() {
+            if torrent.fill_magnet_link() {
+                chunk_filled += 1;
+            } else if torrent.magnet_link.is_none() {
+                skipped += 1;
+            }
+        }
+        if chunk_filled > 0 {
+            Stash::write_chunk(chunk_id, &chunk, &missing_checks)?;
+            filled += chunk_filled;
+        }
+    }
+    info!("Backfilled {filled} magnet links, skipped {skipped} torrents with an invalid infohash");
+    Ok(())
+}
+
+/// Checks that the magnet links of all stashed torrents are fit for publishing, printing the ids of the invalid ones
+fn validate_magnets() -> Result<(), anyhow::Error> {
+    let (mut checked, mut invalid) = (0, 0);
+    for chunk_id in Stash::chunk_ids()? {
+        for (id, torrent) in Stash::read_chunk(chunk_id)? {
+            let Some(torrent) = torrent else {
+                continue;
+            };
+            checked += 1;
+            let problems = magnet::problems(&torrent.magnet_link());
+            if !problems.is_empty() {
+                println!("{id}: {}", problems.join(", "));
+                invalid += 1;
+            }
+        }
+    }
+
+    if invalid > 0 {
+        bail!("{invalid} of {checked} magnet links are invalid");
+    }
+    info!("All {checked} magnet links are valid");
+    Ok(())
+}
+
+/// Recomputes the derived fields of all stashed torrents without any request, rewriting the chunks where some changed
+fn reindex() -> Result<(), anyhow::Error> {
+    let (mut updated, mut total) = (0, 0);
+    for chunk_id in Stash::chunk_ids()? {
+        let (mut chunk, missing_checks) = Stash::read_merged(chunk_id)?;
+        let mut chunk_updated = 0;
+        for torrent in chunk.values_mut().flatten() {
+            total += 1;
+            if torrent.reindex() {
+                chunk_updated += 1;
+            }
+        }
+        if chunk_updated > 0 {
+            Stash::write_chunk(chunk_id, &chunk, &missing_checks)?;
+            updated += chunk_updated;
+        }
+    }
+    info!("Reindexed {total} torrents, {updated} of which changed");
+    Ok(())
+}
+
+fn stats() -> Result<(), anyhow::Error> {
+    let present = Stash::count_present()?;
+    let missing = Stash::count_missing()?;
+    println!("Torrents: {present}");
+    println!("Missing ids: {missing}");
+    println!("Checked ids: {}", present + missing);
+    Ok(())
+}
+
+/// Summary of a torrent printed by `top`
+#[derive(Serialize)]
+struct TopEntry {
+    id: usize,
+    name: String,
+    category: String,
+    seeders: usize,
+    total_size: u64,
+    downloads: usize,
+}
+
+/// Returns the torrents ranking highest on a metric, best first, holding no more than `limit` of them in memory
+fn top_entries(config: &Config) -> Result<Vec<TopEntry>, anyhow::Error> {
+    // Min-heap on the metric, so that the lowest ranked torrent is the one evicted
+    let mut heap = BinaryHeap::with_capacity(config.limit + 1);
+    for chunk_id in Stash::chunk_ids()? {
+        for (id, torrent) in Stash::read_chunk(chunk_id)? {
+            let Some(torrent) = torrent else { continue };
+            let key = match config.by {
+                RankBy::Seeders => torrent.seeders as u64,
+                RankBy::Size => torrent.total_size.as_u64(),
+                RankBy::Downloads => torrent.downloads as u64,
+            };
+            if heap.len() == config.limit && heap.peek().is_some_and(|Reverse((lowest, _))| key <= *lowest) {
+                continue;
+            }
+            let entry = TopEntry {
+                id,
+                name: torrent.name,
+                category: torrent.category,
+                seeders: torrent.seeders,
+                total_size: torrent.total_size.as_u64(),
+                downloads: torrent.downloads,
+            };
+            heap.push(Reverse((key, TopRank(entry))));
+            if heap.len() > config.limit {
+                heap.pop();
+            }
+        }
+    }
+
+    Ok(heap.into_sorted_vec().into_iter().map(|Reverse((_, TopRank(entry)))| entry).collect())
+}
+
+/// Prints the torrents ranking highest on a metric
+fn top(config: &Config) -> Result<(), anyhow::Error> {
+    let entries = top_entries(config)?;
+    if config.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    println!("{:>9}  {:>8}  {:>10}  {:>9}  {:<13}  Name", "Id", "Seeders", "Size", "Downloads", "Category");
+    for entry in entries {
+        println!("{:>9}  {:>8}  {:>10}  {:>9}  {:<13}  {}", entry.id, entry.seeders, entry.total_size, entry.downloads, entry.category, entry.name);
+    }
+    Ok(())
+}
+
+/// Orders entries by id alone, so that ties on the metric favor lower ids
+struct TopRank(TopEntry);
+
+impl PartialEq for TopRank {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.id == other.0.id
+    }
+}
+
+impl Eq for TopRank {}
+
+impl PartialOrd for TopRank {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TopRank {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.0.id.cmp(&self.0.id)
+    }
+}
+
+fn prune(config: &Config, clock: &dyn Clock) -> Result<(), anyhow::Error> {
+    let days = config.missing_older_than.ok_or_else(|| anyhow!("prune requires --missing-older-than"))?;
+    let now = clock.now();
+    let pruned = Stash::prune_missing(now, now.saturating_sub(days * 86400))?;
+    info!("Pruned {pruned} missing entries");
+    Ok(())
+}
+
+fn refresh(config: &Config, client: &dyn HttpClient, clock: &dyn Clock) -> Result<(), anyhow::Error> {
+    let mut stash = Stash::open()?.with_omitted_fields(config.omit_fields.clone()).with_append_only(config.append_only);
+    let mut rng = delay_rng(config);
+
+    let mut refreshed = 0;
+    for chunk_id in Stash::chunk_ids()? {
+        for (i, previous) in Stash::read_chunk(chunk_id)? {
+            let Some(previous) = previous else {
+                continue;
+            };
+            if previous.next_refresh_ts > clock.now() {
+                continue;
+            }
+
+            match scrape_torrent(config, client, clock, i, Some(&previous)) {
+                Ok(info) => {
+                    stash.insert(i, info, clock.now())?;
+                    refreshed += 1;
+                }
+                Err(err) => error!("Failed to refresh torrent {i}: {err}"),
+            }
+
+            std::thread::sleep(inter_request_delay(config, &mut rng));
+        }
+    }
+    stash.save()?;
+
+    info!("Refreshed {refreshed} torrents");
+    Ok(())
+}
+
+/// Scrapes the torrents of a trending page into the stash
+fn scrape_trending(config: &Config, client: &dyn HttpClient, clock: &dyn Clock) -> Result<(), anyhow::Error> {
+    let url = trending_url(&config.base_url, config.period);
+    let resp = client.get(&url)?;
+    if resp.status_code != 200 {
+        bail!("Unexpected status code for {url}: {}", resp.status_code);
+    }
+    let ids = parse_listing_ids(&resp.text());
+    if ids.is_empty() {
+        bail!("No torrents found on {url}");
+    }
+    info!("Found {} trending torrents", ids.len());
+
+    let mut stash = Stash::open()?.with_omitted_fields(config.omit_fields.clone()).with_append_only(config.append_only);
+    let mut rng = delay_rng(config);
+    let mut scraped = 0;
+    for i in ids {
+        match scrape_torrent(config, client, clock, i, None) {
+            Ok(info) => {
+                stash.insert(i, info, clock.now())?;
+                scraped += 1;
+            }
+            Err(err) => error!("Failed to scrape torrent {i}: {err}"),
+        }
+        std::thread::sleep(inter_request_delay(config, &mut rng));
+    }
+    stash.save()?;
+
+    info!("Scraped {scraped} trending torrents");
+    Ok(())
+}
+
+/// Scrapes torrents breadth-first from `--seed-ids`, following the torrents each page links to.
+/// Linked torrents already in the stash aren't scraped again, so the crawl doesn't expand through them.
+fn crawl(config: &Config, client: &dyn HttpClient, clock: &dyn Clock) -> Result<(), anyhow::Error> {
+    if config.seed_ids.is_empty() {
+        bail!("crawl requires --seed-ids");
+    }
+
+    let mut stash = Stash::open()?.with_omitted_fields(config.omit_fields.clone()).with_append_only(config.append_only);
+    let mut rng = delay_rng(config);
+    let mut frontier = config.seed_ids.iter().copied().collect::<VecDeque<_>>();
+    let mut visited = config.seed_ids.iter().copied().collect::<BTreeSet<_>>();
+    let mut scraped = 0;
+    while let Some(i) = frontier.pop_front() {
+        if config.count.is_some_and(|count| scraped >= count) {
+            break;
+        }
+
+        match scrape_torrent(config, client, clock, i, None) {
+            Ok(mut info) => {
+                if let Some(torrent) = &info {
+                    debug!("Scraped torrent {i}: {}", torrent.name);
+                    for linked_id in &torrent.linked_ids {
+                        if visited.insert(*linked_id) && !config.is_excluded(*linked_id) && !stash.contains_key(linked_id)? {
+                            frontier.push_back(*linked_id);
+                        }
+                    }
+                    if let Some(reason) = rejection_reason(config, torrent) {
+                        debug!("Not storing torrent {i}: {reason}");
+                        info = None;
+                    }
+                }
+                stash.insert(i, info, clock.now())?;
+                scraped += 1;
+            }
+            Err(err) => error!("Failed to scrape torrent {i}: {err}"),
+        }
+        std::thread::sleep(inter_request_delay(config, &mut rng));
+    }
+    stash.save()?;
+
+    info!("Crawled {scraped} torrents, {} left in the frontier", frontier.len());
+    Ok(())
+}
+
+/// Extracts the ids of the "Failed to scrape torrent <id>" lines of a log
+fn failed_ids_in_log(log: &str) -> BTreeSet<usize> {
+    const MARKER: &str = "Failed to scrape torrent ";
+    log.lines()
+        .filter_map(|line| line.split_once(MARKER))
+        .filter_map(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok())
+        .collect()
+}
+
+/// Scrapes again the torrents that failed according to a log, reporting which now succeed
+fn replay_errors(config: &Config, client: &dyn HttpClient, clock: &dyn Clock) -> Result<(), anyhow::Error> {
+    let path = config.log.as_ref().ok_or_else(|| anyhow!("replay-errors requires --log"))?;
+    let ids = failed_ids_in_log(&std::fs::read_to_string(path)?);
+    info!("Found {} failed torrents in {}", ids.len(), path.display());
+
+    let mut stash = Stash::open()?.with_omitted_fields(config.omit_fields.clone()).with_append_only(config.append_only);
+    let mut rng = delay_rng(config);
+    let mut still_failing = Vec::new();
+    for &i in &ids {
+        match scrape_torrent(config, client, clock, i, None) {
+            Ok(info) => {
+                info!("Torrent {i} now succeeds");
+                stash.insert(i, info, clock.now())?;
+            }
+            Err(err) => {
+                error!("Torrent {i} still fails: {err}");
+                still_failing.push(i);
+            }
+        }
+        std::thread::sleep(inter_request_delay(config, &mut rng));
+    }
+    stash.save()?;
+
+    info!("{} of {} torrents now succeed", ids.len() - still_failing.len(), ids.len());
+    if !still_failing.is_empty() {
+        println!("{}", still_failing.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+    }
+    Ok(())
+}
+
+/// Scrapes again the torrents whose files or comments are missing
+fn refill(config: &Config, client: &dyn HttpClient, clock: &dyn Clock) -> Result<(), anyhow::Error> {
+    if config.missing.is_empty() {
+        bail!("refill requires --missing");
+    }
+
+    let mut stash = Stash::open()?.with_omitted_fields(config.omit_fields.clone()).with_append_only(config.append_only);
+    let mut rng = delay_rng(config);
+
+    let (mut attempted, mut filled) = (0, 0);
+    for chunk_id in Stash::chunk_ids()? {
+        for (i, previous) in Stash::read_chunk(chunk_id)? {
+            let Some(previous) = previous else {
+                continue;
+            };
+            let category = previous.normalized_category();
+            let missing = config.missing.iter().copied()
+                .filter(|part| previous.is_missing(*part) && !config.omits(&category, part.field()))
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                continue;
+            }
+
+            attempted += 1;
+            match scrape_torrent(config, client, clock, i, None) {
+                Ok(Some(torrent)) => {
+                    if missing.iter().any(|part| !torrent.is_missing(*part)) {
+                        debug!("Filled torrent {i}");
+                        filled += 1;
+                    }
+                    stash.insert(i, Some(torrent), clock.now())?;
+                }
+                Ok(None) => warn!("Torrent {i} disappeared, keeping it as is"),
+                Err(err) => error!("Failed to refill torrent {i}: {err}"),
+            }
+
+            std::thread::sleep(inter_request_delay(config, &mut rng));
+        }
+    }
+    stash.save()?;
+
+    info!("Filled {filled} of {attempted} incomplete torrents");
+    Ok(())
+}
+
+/// Reads the highest id an incremental scan found a torrent at, if it ever ran
+fn read_since_id(path: &std::path::Path) -> Result<Option<usize>, anyhow::Error> {
+    match std::fs::read_to_string(path) {
+        Ok(data) => Ok(Some(data.trim().parse().map_err(|err| anyhow!("Invalid id in {}: {err}", path.display()))?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Records the highest id holding a torrent for the next incremental scan, when `--since-id-file` is set
+fn write_since_id(config: &Config, max_found_id: Option<usize>) -> Result<(), anyhow::Error> {
+    if let (Some(path), Some(max_found_id)) = (&config.since_id_file, max_found_id) {
+        std::fs::write(path, max_found_id.to_string())?;
+    }
+    Ok(())
+}
+
+/// How often a paused scan checks whether it entered its active hours, short in tests so that they don't wait
+const ACTIVE_HOURS_CHECK_INTERVAL: Duration = match cfg!(test) {
+    true => Duration::from_millis(1),
+    false => Duration::from_secs(60),
+};
+
+/// Seconds until a scan looks again at a torrent being processed
+const PROCESSING_RETRY_DELAY: u64 = 600;
+
+/// Times a scan looks again at a torrent being processed before leaving it to `replay-errors`
+const PROCESSING_RETRIES: usize = 3;
+
+/// Returns the id a scan resumes at, which is the lowest id still being processed if any so that it isn't skipped
+fn scan_cursor(next_id: usize, processing: &VecDeque<(u64, usize, usize)>) -> usize {
+    processing.iter().map(|&(_, id, _)| id).fold(next_id, usize::min)
+}
+
+/// Returns the space left on the stash disk if it's below `--min-free-disk`
+fn low_disk_space(config: &Config, disk: &dyn DiskSpace) -> Option<u64> {
+    let min_free_disk = config.min_free_disk?;
+    match disk.available(Path::new(&stash::dir())) {
+        Ok(available) => (available < min_free_disk).then_some(available),
+        Err(err) => {
+            warn!("Failed to check free disk space: {err}");
+            None
+        }
+    }
+}
+
+fn scan(config: &Config, client: &dyn HttpClient, clock: &dyn Clock, disk: &dyn DiskSpace) -> Result<(), anyhow::Error> {
+    let mut stash = Stash::open()?.with_omitted_fields(config.omit_fields.clone()).with_append_only(config.append_only);
+    let mut rng = delay_rng(config);
+    let start = Instant::now();
+    let mut queries = 0;
+    let mut counted = 0;
+    let mut consecutive_errors: usize = 0;
+    let hook = config.on_scrape.clone().map(ScrapeHook::spawn);
+    let mut bulk = config.bulk.then(BulkScraper::default);
+    let since_id = config.since_id_file.as_deref().map(read_since_id).transpose()?.flatten();
+    let mut max_found_id = since_id;
+    // Torrents being processed, as the time they are due again, their id, and how many times they were tried
+    let mut processing = VecDeque::new();
+    let mut next_id = match (since_id, config.resume_from_cursor) {
+        (Some(since_id), _) => since_id + 1,
+        (None, true) => Stash::read_cursor()?.map_or(config.start_id, |cursor| cursor.max(config.start_id)),
+        (None, false) => config.start_id,
+    };
+    info!("Starting scan at id {next_id}");
+    loop {
+        if config.count.is_some_and(|count| counted >= count) {
+            break;
+        }
+        if let Some(active_hours) = config.active_hours.filter(|active_hours| !active_hours.contains(clock.now())) {
+            info!("Outside of active hours, pausing until {}", active_hours.start);
+            if let Err(err) = stash.save().and_then(|()| Stash::write_cursor(scan_cursor(next_id, &processing))) {
+                error!("{err:#}");
+            }
+            while !active_hours.contains(clock.now()) {
+                std::thread::sleep(ACTIVE_HOURS_CHECK_INTERVAL);
+            }
+            info!("Within active hours, resuming the scan");
+        }
+        // Stop before the disk fills up, as failing writes would leave progress in memory only
+        if let Some(available) = low_disk_space(config, disk) {
+            error!("Only {available} bytes left on the stash disk, stopping the scan");
+            break;
+        }
+        let (i, attempts) = match processing.front() {
+            Some(&(due_ts, id, attempts)) if due_ts <= clock.now() => {
+                processing.pop_front();
+                (id, attempts)
+            }
+            _ => {
+                next_id += 1;
+                (next_id - 1, 0)
+            }
+        };
+
+        if config.is_excluded(i) {
+            trace!("Skipping excluded id {i}");
+            continue;
+        }
+        // Incremental scans look again at ids that were missing, as new torrents show up there
+        let known = match config.since_id_file.is_some() {
+            true => stash.holds_torrent(i)?,
+            false => stash.contains_key(&i)?,
+        };
+        if known {
+            if config.since_id_file.is_some() {
+                max_found_id = max_found_id.max(Some(i));
+            }
+            if config.count_skipped {
+                counted += 1;
+            }
+            continue;
+        }
+
+        // Bulk responses don't tell torrents being processed apart, so their pages are scraped
+        let result = match bulk.as_mut().filter(|_| attempts == 0).and_then(|bulk| bulk.scrape(config, client, clock, i)) {
+            Some(result) => result,
+            None => scrape_torrent(config, client, clock, i, None),
+        };
+        match result {
+            Ok(mut info) => {
+                if let Some(torrent) = &info {
+                    debug!("Scraped torrent {i}: {}", torrent.name);
+                    max_found_id = max_found_id.max(Some(i));
+
+                    // Unwanted torrents are still recorded so that they don't get scraped again
+                    if let Some(reason) = rejection_reason(config, torrent) {
+                        debug!("Not storing torrent {i}: {reason}");
+                        info = None;
+                    }
+                }
+                if let (true, Some(torrent)) = (config.emit_ndjson, &info) {
+                    let mut stdout = std::io::stdout().lock();
+                    export::write_record(&mut stdout, i, torrent)?;
+                    stdout.flush()?;
+                }
+                if let (Some(hook), Some(torrent)) = (&hook, &info) {
+                    if hook_wants(config, torrent) {
+                        hook.send(i, serde_json::to_string(torrent)?);
+                    }
+                }
+                stash.insert(i, info, clock.now())?;
+                consecutive_errors = 0;
+            }
+            Err(err) if err.is::<BeingProcessed>() && attempts < PROCESSING_RETRIES => {
+                info!("Torrent {i} is being processed, looking at it again in {PROCESSING_RETRY_DELAY}s");
+                processing.push_back((clock.now() + PROCESSING_RETRY_DELAY, i, attempts + 1));
+            }
+            Err(err) => {
+                error!("Failed to scrape torrent {i}: {err}");
+                consecutive_errors += 1;
+                // Error bursts often precede a crash or a block, so progress is saved right away
+                if config.checkpoint_on_error.is_some_and(|n| n > 0 && consecutive_errors.is_multiple_of(n)) {
+                    warn!("{consecutive_errors} consecutive errors, saving progress");
+                    if let Err(err) = stash.save() {
+                        error!("{err:#}");
+                    }
+                }
+            }
+        }
+        queries += 1;
+        counted += 1;
+
+        if i.is_multiple_of(80) {
+            debug!("Saving data");
+            if let Err(err) = stash.save().and_then(|()| Stash::write_cursor(scan_cursor(next_id, &processing))).and_then(|()| write_since_id(config, max_found_id)) {
+                error!("{err:#}");
+            }
+            let ms_per_query = start.elapsed().as_millis() as f64 / queries as f64;
+            let remaining_queries = 5559585 - queries;
+            let percentage = (i as f64 / 5559585.0) * 100.0;
+            let remaining_hours = (remaining_queries as f64 * ms_per_query) / 1000.0 / 60.0 / 60.0;
+            debug!("Saved data");
+            info!("We scraped {percentage:.2}% of torrents. At the current rate, we will finish in {remaining_hours:.2} hours.");
+        }
+
+        // Ids served from a bulk response need no request, so no delay either
+        if bulk.as_ref().is_none_or(|bulk| !bulk.serves(next_id)) {
+            std::thread::sleep(inter_request_delay(config, &mut rng));
+        }
+    }
+    stash.save()?;
+    Stash::write_cursor(scan_cursor(next_id, &processing))?;
+    write_since_id(config, max_found_id)?;
+
+    // Logged like other failures so that `replay-errors` picks them up
+    for (_, i, _) in processing {
+        error!("Failed to scrape torrent {i}: {BeingProcessed}");
+    }
+    info!("Scraped {queries} torrents, up to id {}", next_id - 1);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    const PAGE: &str = include_str!("../test.html");
+    const NAME: &str = "The.Weapon.2023.1080p.WEBRip.DDP5.1.x265-Asiimov";
+
+    fn parse_page(page: &str) -> TorrentInfo {
+        parse_torrent_html(page, NOW, &Config::default().comment_count_selector).unwrap().unwrap()
+    }
+
+    #[test]
+    fn parses_fixture_page() {
+        let torrent = parse_page(PAGE);
+        assert_eq!(torrent.name, NAME);
+        assert_eq!(torrent.language, "English");
+        assert_eq!(torrent.infohash.as_str(), "4738fb09f6ae3e2f45bca0ffec9f32779500ab03");
+        assert_eq!(torrent.total_size, ByteSize::from((1.3 * 1024.0 * 1024.0 * 1024.0) as u64));
+        assert_eq!(torrent.uploader, "PBDR");
+        assert!(torrent.description.starts_with("RUNTiME"), "{:?}", torrent.description);
+    }
+
+    #[test]
+    fn writes_a_torrent_in_each_format() {
+        let torrent = parse_page(PAGE);
+        let output = |format| {
+            let mut out = Vec::new();
+            write_torrent(&mut out, &Config { format, ..Config::default() }, Some(&torrent)).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let json = output(OutputFormat::Json);
+        assert_eq!(json.lines().count(), 1);
+        let pretty = output(OutputFormat::Pretty);
+        assert!(pretty.lines().count() > 1);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), serde_json::from_str::<serde_json::Value>(&pretty).unwrap());
+        assert_eq!(serde_json::from_str::<TorrentInfo>(&json).unwrap().name, NAME);
+
+        let text = output(OutputFormat::Text);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], format!("Name:     {NAME}"));
+        assert_eq!(lines[1], format!("Size:     {}", torrent.total_size));
+        assert_eq!(lines[2], "Seeders:  325 (128 leechers)");
+        assert_eq!(lines[3], "Uploader: PBDR");
+        assert!(lines[5].starts_with("Magnet:   magnet:?xt=urn:btih:"), "{}", lines[5]);
+
+        let mut out = Vec::new();
+        write_torrent(&mut out, &Config { format: OutputFormat::Text, ..Config::default() }, None).unwrap();
+        assert_eq!(out, b"No torrent found\n");
+    }
+
+    #[test]
+    fn name_ignores_nested_badges() {
+        let page = PAGE.replace(
+            &format!("<h1> {NAME} </h1>"),
+            &format!("<h1> <span class=\"badge\">VIP</span> {NAME} <i class=\"flaticon-hd\">HD</i></h1>"),
+        );
+        assert_ne!(page, PAGE);
+        assert_eq!(parse_page(&page).name, NAME);
+    }
+
+    #[test]
+    fn strips_description_line_starting_with_name() {
+        let page = PAGE.replacen("<p></p>", &format!("<p>{NAME} - remux notes</p><p>Second line</p>"), 1);
+        let torrent = parse_page(&page);
+        assert_eq!(torrent.name, NAME);
+        assert!(torrent.description.starts_with("Second line"), "{:?}", torrent.description);
+    }
+
+    #[test]
+    fn unparsable_files_are_recorded_as_warnings() {
+        let page = PAGE.replacen("(1.3 GB)</li>", "(1.3 GB)</li><li>sample.mkv (huge)</li>", 1);
+        assert_ne!(page, PAGE);
+        let torrent = parse_page(&page);
+        assert_eq!(torrent.files.len(), 1);
+        assert_eq!(torrent.warnings, ["Failed to parse file: sample.mkv (huge)"]);
+        assert!(parse_page(PAGE).warnings.is_empty());
+    }
+
+    #[test]
+    fn reads_the_displayed_file_count() {
+        let torrent = parse_page(PAGE);
+        assert_eq!(torrent.file_count, None);
+        assert!(!torrent.is_missing(RecordPart::Files));
+
+        let page = PAGE.replace("<h2>Files: </h2>", "<h2>Files: 3</h2>");
+        assert_ne!(page, PAGE);
+        let torrent = parse_page(&page);
+        assert_eq!(torrent.file_count, Some(3));
+        assert_eq!(torrent.files.len(), 1);
+        assert!(torrent.is_missing(RecordPart::Files));
+
+        let page = PAGE.replace("data-toggle=\"tab\">Files</a>", "data-toggle=\"tab\">Files (1)</a>");
+        assert_ne!(page, PAGE);
+        let torrent = parse_page(&page);
+        assert_eq!(torrent.file_count, Some(1));
+        assert!(!torrent.is_missing(RecordPart::Files));
+    }
+
+    #[test]
+    fn reads_the_verified_badge() {
+        assert!(!parse_page(PAGE).verified);
+        for badge in [r#"<span class="verified"></span>"#, r#"<i class="flaticon-verified"></i>"#, r#"<img title="Verified" src="/images/verified.png">"#] {
+            let page = PAGE.replace(&format!("<h1> {NAME} </h1>"), &format!("<h1> {NAME} </h1>{badge}"));
+            assert_ne!(page, PAGE);
+            assert!(parse_page(&page).verified, "{badge}");
+        }
+    }
+
+    #[test]
+    fn reads_lazy_loaded_and_plain_images() {
+        let fixture_images = parse_page(PAGE).images;
+        let images = concat!(
+            r#"<img class="descrimg" src="/images/profile-load.svg" data-original="https://i.example/lazy.jpg">"#,
+            r#"<img src="https://i.example/plain.png">"#,
+            r#"<img src="data:image/gif;base64,R0lGOD">"#,
+            r#"<img src="https://t.example/pixel.gif" width="1" height="1">"#,
+            r#"<img src="/images/profile-load.svg">"#,
+        );
+        let page = PAGE.replacen("<p></p>", &format!("<p>{images}</p>"), 1);
+        let expected = ["https://i.example/lazy.jpg", "https://i.example/plain.png"].map(String::from).into_iter().chain(fixture_images).collect::<Vec<_>>();
+        assert_eq!(parse_page(&page).images, expected);
+    }
+
+    #[test]
+    fn breadcrumbs_win_over_info_spans() {
+        let with_breadcrumbs = |category: &str, ty: &str| PAGE.replacen(
+            "<div class=\"col-9 page-content\">",
+            &format!(r#"<div class="col-9 page-content"><ul class="breadcrumb"><li><a href="/">Home</a></li><li><a href="/cat/Movies/1/">{category}</a></li><li><a href="/sub/x/1/">{ty}</a></li></ul>"#),
+            1,
+        );
+        let torrent = parse_page(&with_breadcrumbs("Movies", "HEVC/x265"));
+        assert_eq!((torrent.category.as_str(), torrent.ty.as_str()), ("Movies", "HEVC/x265"));
+        assert!(torrent.warnings.is_empty(), "{:?}", torrent.warnings);
+
+        let torrent = parse_page(&with_breadcrumbs("TV", "HD"));
+        assert_eq!((torrent.category.as_str(), torrent.ty.as_str()), ("TV", "HD"));
+        assert_eq!(torrent.warnings, [
+            "Category mismatch: breadcrumbs say \"TV\", page says \"Movies\"",
+            "Type mismatch: breadcrumbs say \"HD\", page says \"HEVC/x265\"",
+        ]);
+    }
+
+    #[test]
+    fn dead_torrents_lack_peers() {
+        let page = PAGE
+            .replace("<li> <strong>Seeders</strong> <span class=\"seeds\">325</span> </li>", "")
+            .replace("<li> <strong>Leechers</strong> <span class=\"leeches\">128</span> </li>", "");
+        assert_ne!(page, PAGE);
+        let torrent = parse_page(&page);
+        assert_eq!((torrent.seeders, torrent.leechers), (0, 0));
+        assert_eq!(torrent.downloads, 1931);
+        assert_eq!(torrent.uploaded_ts, NOW - 86400);
+
+        let page = page.replace("<li> <strong>Downloads</strong> <span>1931</span> </li>", "");
+        let err = parse_torrent_html(&page, NOW, COMMENT_COUNT_SELECTOR).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected number of spans: 7");
+    }
+
+    #[test]
+    fn peers_have_their_own_checked_time() {
+        let torrent = parse_page(PAGE);
+        assert_eq!(torrent.last_checked_ts, NOW - 26 * 60);
+        assert_eq!(torrent.peers_checked_ts, None);
+
+        let seeders = "<span class=\"seeds\">325</span> </li>";
+        let page = PAGE.replace(seeders, "<span class=\"seeds\">325</span> (as of 5 minutes ago)</li>");
+        assert_ne!(page, PAGE);
+        let torrent = parse_page(&page);
+        assert_eq!(torrent.last_checked_ts, NOW - 26 * 60);
+        assert_eq!(torrent.peers_checked_ts, Some(NOW - 5 * 60));
+        assert_eq!(torrent.seeders, 325);
+
+        let torrent = parse_page(&PAGE.replace(seeders, "<span class=\"seeds\">325</span> (as of yesterday-ish)</li>"));
+        assert_eq!(torrent.peers_checked_ts, None);
+        assert_eq!(torrent.warnings, ["Invalid peers checked time: \"yesterday-ish\""]);
+    }
+
+    #[test]
+    fn strips_labels_from_the_infohash_box() {
+        const HASH: &str = "4738FB09F6AE3E2F45BCA0FFEC9F32779500AB03";
+        for label in ["Infohash: ", "INFO HASH :", "hash:"] {
+            let page = PAGE.replace(&format!("<span>{HASH}</span>"), &format!("<span>{label}{HASH} </span>"));
+            assert_ne!(page, PAGE);
+            let torrent = parse_page(&page);
+            assert_eq!(torrent.infohash.as_str(), HASH.to_ascii_lowercase());
+            assert!(torrent.warnings.is_empty());
+        }
+        assert_eq!(strip_infohash_label(HASH), HASH);
+        // Only labels followed by a colon are stripped
+        assert_eq!(strip_infohash_label("hashes"), "hashes");
+    }
+
+    #[test]
+    fn missing_infohash_errors_name_the_selector() {
+        let page = PAGE.replace("<span>4738FB09F6AE3E2F45BCA0FFEC9F32779500AB03</span>", "4738FB09F6AE3E2F45BCA0FFEC9F32779500AB03");
+        assert_ne!(page, PAGE);
+        let err = parse_torrent_html(&page, NOW, COMMENT_COUNT_SELECTOR).unwrap_err().to_string();
+        assert!(err.starts_with("No infohash found: selector \".infohash-box>p>span\" matched nothing, closest match is \".infohash-box>p\": <p> <strong>Infohash :</strong> 4738FB09"), "{err}");
+    }
+
+    #[test]
+    fn completes_truncated_name_from_description() {
+        let truncated = &NAME[..20];
+        let page = PAGE.replace(&format!("<h1> {NAME} </h1>"), &format!("<h1> {truncated}... </h1>"));
+        let torrent = parse_page(&page);
+        assert_eq!(torrent.name, NAME);
+        assert!(torrent.description.starts_with("RUNTiME"), "{:?}", torrent.description);
+    }
+
+    #[test]
+    fn parses_combined_and_fractional_offsets() {
+        assert_eq!(parse_time_offset(NOW, "1 hour 30 minutes ago"), Some(NOW - 5400));
+        assert_eq!(parse_time_offset(NOW, "1.5 hours ago"), Some(NOW - 5400));
+        assert_eq!(parse_time_offset(NOW, "2 days, 3 hours and 4 minutes ago"), Some(NOW - 2 * 86400 - 3 * 3600 - 240));
+        assert_eq!(parse_time_offset(NOW, "3 years ago"), Some(NOW - 3 * 365 * 86400));
+        assert_eq!(parse_time_offset(NOW, "just now"), Some(NOW));
+    }
+
+    #[test]
+    fn normalizes_unicode_spaces() {
+        assert_eq!(parse_time_offset(NOW, "2\u{a0}hours\u{a0}ago"), Some(NOW - 7200));
+        assert_eq!(parse_time_offset(NOW, "\u{202f}1 hour\u{2009}30 minutes ago "), Some(NOW - 5400));
+        assert_eq!(parse_data_size("1.3\u{a0}GB"), Some((1.3 * 1024.0 * 1024.0 * 1024.0) as u64));
+        assert_eq!(parse_data_size(" 512\u{a0}\u{a0}KB"), Some(512 * 1024));
+        assert_eq!(parse_data_size("1,024 MB"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_data_size("1.3GB"), None);
+    }
+
+    #[test]
+    fn clamps_offsets_slightly_in_the_future() {
+        assert_eq!(parse_time_offset(NOW, "1 second ago"), Some(NOW - 1));
+        assert_eq!(parse_time_offset(NOW, "0 seconds ago"), Some(NOW));
+        assert_eq!(parse_time_offset(NOW, "-0.4 seconds ago"), Some(NOW));
+        assert_eq!(parse_time_offset(NOW, "-1 second ago"), Some(NOW));
+        assert_eq!(parse_time_offset(NOW, "-300 seconds ago"), Some(NOW));
+        assert_eq!(parse_time_offset(NOW, "-5 minutes ago"), Some(NOW));
+        assert_eq!(parse_time_offset(NOW, "-301 seconds ago"), None);
+        assert_eq!(parse_time_offset(NOW, "-1 hour ago"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_offsets() {
+        assert_eq!(parse_time_offset(NOW, ""), None);
+        assert_eq!(parse_time_offset(NOW, "1 hour"), None);
+        assert_eq!(parse_time_offset(NOW, "hour ago"), None);
+        assert_eq!(parse_time_offset(NOW, "1 fortnight ago"), None);
+        assert_eq!(parse_time_offset(NOW, "NaN hours ago"), None);
+    }
+
+    #[test]
+    fn reads_the_first_integer() {
+        assert_eq!(first_integer("12"), Some(12));
+        assert_eq!(first_integer("(12)"), Some(12));
+        assert_eq!(first_integer("12 Comments"), Some(12));
+        assert_eq!(first_integer("Comments"), None);
+    }
+
+    #[test]
+    fn reads_ids_from_a_trending_page() {
+        let row = |id: usize, name: &str| format!(concat!(
+            r#"<tr><td class="coll-1 name"><a href="/sub/42/0/" class="icon"><i class="flaticon-hd"></i></a>"#,
+            r#"<a href="/torrent/{id}/{name}/">{name}</a><span class="comments"><i class="flaticon-message"></i>3</span></td>"#,
+            r#"<td class="coll-2 seeds">1200</td><td class="coll-3 leeches">300</td><td class="coll-date">9am Oct. 16th</td>"#,
+            r#"<td class="coll-4 size mob-uploader">1.3 GB<span class="seeds">1200</span></td><td class="coll-5 uploader"><a href="/user/PBDR/">PBDR</a></td></tr>"#,
+        ), id = id, name = name);
+        let page = format!(
+            r#"<html><body><div class="featured-list"><div class="box-info-heading"><h1>Top 100 trending torrents this week</h1></div><table class="table-list"><tbody>{}{}{}</tbody></table></div><a href="/torrent/">All</a></body></html>"#,
+            row(5558130, "The-Weapon-2023"), row(5557000, "Another-One"), row(5558130, "The-Weapon-2023"),
+        );
+        assert_eq!(parse_listing_ids(&page), [5558130, 5557000]);
+    }
+
+    #[test]
+    fn splits_urls() {
+        assert_eq!(split_url("https://1337x.to/torrent/1/"), Some(("1337x.to", "/torrent/1/")));
+        assert_eq!(split_url("http://127.0.0.1:8080?a"), Some(("127.0.0.1", "?a")));
+        assert_eq!(split_url("http://[::1]:8080/x"), Some(("::1", "/x")));
+        assert_eq!(split_url("ftp://1337x.to/"), None);
+        assert_eq!(split_url("https:///torrent"), None);
+    }
+
+    #[test]
+    fn reads_torrent_ids_from_urls() {
+        let base = "https://1337x.torrentbay.to";
+        assert_eq!(torrent_id_from_url("https://1337x.to/torrent/5558130/Some-Slug/", base).unwrap(), 5558130);
+        assert_eq!(torrent_id_from_url("https://www.1337x.to/torrent/42?ref=home#comments", base).unwrap(), 42);
+        assert_eq!(torrent_id_from_url("http://127.0.0.1:8080/torrent/7/", "http://127.0.0.1:8080").unwrap(), 7);
+        assert!(torrent_id_from_url("https://example.com/torrent/42/", base).is_err());
+        assert!(torrent_id_from_url("https://1337x.to/user/42/", base).is_err());
+        assert!(torrent_id_from_url("https://1337x.to/torrent/slug/", base).is_err());
+        assert!(torrent_id_from_url("1337x.to/torrent/42/", base).is_err());
+    }
+
+    #[test]
+    fn builds_torrent_and_comments_urls() {
+        let base = "https://1337x.to";
+        assert_eq!(torrent_url(base, 5558130), "https://1337x.to/torrent/5558130/friendly-scraper/");
+        assert_eq!(comments_url(base, "/comments.php?torrentid={id}", 5558130, 1), "https://1337x.to/comments.php?torrentid=5558130");
+        assert_eq!(comments_url(base, "/api/{id}/comments?page={page}", 42, 3), "https://1337x.to/api/42/comments?page=3");
+        assert_eq!(torrent_id_from_url(&torrent_url(base, 42), base).unwrap(), 42);
+    }
+
+    /// The real minreq client, for tests against a `FixtureServer`
+    fn fixture_client() -> MinreqClient {
+        MinreqClient { settings: ClientSettings::default() }
+    }
+
+    #[test]
+    fn scrapes_torrent_and_comments_from_a_server() {
+        use fixture_server::{FixtureServer, Route};
+
+        let page = PAGE.replace("Comments<span class=\"active\">0</span>", "Comments<span class=\"active\">2</span>");
+        let comments = r#"[
+            {"avatar": "", "class": "user", "comment": "Thanks &amp; <b>cheers</b>", "commentid": 1, "posted": "2 hours ago", "username": "alice"},
+            {"avatar": "", "class": null, "comment": "Works", "commentid": 2, "posted": "1 day ago", "username": null}
+        ]"#;
+        let settings = || ClientSettings { user_agent: Some(String::from("friendly-scraper")), ..ClientSettings::default() };
+        let clients: [Box<dyn HttpClient>; 2] = [Box::new(MinreqClient { settings: settings() }), Box::new(pool::PooledClient::new(settings()))];
+        for client in clients {
+            let server = FixtureServer::start([
+                ("/torrent/5558130/friendly-scraper/", Route::ok(page.clone()).header("Set-Cookie", "session=abc; Path=/")),
+                ("/comments.php?torrentid=5558130", Route::ok(comments)),
+            ]);
+            let config = Config { base_url: server.url(), ..Config::default() };
+
+            let torrent = scrape_torrent(&config, client.as_ref(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+            assert_eq!(torrent.name, NAME);
+            assert_eq!(torrent.comment_count, 2);
+            assert_eq!(torrent.comments.len(), 2);
+            assert_eq!(torrent.comments[0].comment, "Thanks & cheers");
+            assert_eq!(torrent.comments[0].posted, NOW - 2 * 3600);
+            assert_eq!(torrent.comments[1].username, "[deleted]");
+            assert!(torrent.warnings.is_empty(), "{:?}", torrent.warnings);
+
+            let requests = server.requests();
+            let paths = requests.iter().map(|request| request.path.as_str()).collect::<Vec<_>>();
+            assert_eq!(paths, ["/torrent/5558130/friendly-scraper/", "/comments.php?torrentid=5558130"]);
+            assert!(requests.iter().all(|request| request.header("user-agent") == Some("friendly-scraper")));
+            assert_eq!(requests[0].header("host"), Some(&server.url()["http://".len()..]));
+            assert_eq!(requests[0].header("cookie"), None);
+            assert_eq!(requests[1].header("cookie"), Some("session=abc"));
+        }
+    }
+
+    #[test]
+    fn scraped_ts_comes_from_the_clock() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([("/torrent/5558130/friendly-scraper/", Route::ok(PAGE))]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(torrent.scraped_ts, NOW);
+
+        let later = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW + 3600), 5558130, None).unwrap().unwrap();
+        assert_eq!(later.scraped_ts, NOW + 3600);
+        assert_eq!(later.uploaded_ts, torrent.uploaded_ts + 3600);
+    }
+
+    #[test]
+    fn reads_comment_karma_and_posts() {
+        let comments: Vec<RawComment> = serde_json::from_str(r#"[
+            {"avatar": "", "class": "user", "comment": "a", "commentid": 1, "posted": "2 hours ago", "username": "alice", "karma": 12, "posts": 340},
+            {"avatar": "", "class": "user", "comment": "b", "commentid": 2, "posted": "2 hours ago", "username": "bob", "reputation": "-3", "total_posts": "1,204"},
+            {"avatar": "", "class": "user", "comment": "c", "commentid": 3, "posted": "2 hours ago", "username": "carol", "karma": "n/a", "postcount": null},
+            {"avatar": "", "class": "user", "comment": "d", "commentid": 4, "posted": "2 hours ago", "username": "dave"}
+        ]"#).unwrap();
+        let stats = comments.iter().map(|comment| (comment.karma, comment.posts)).collect::<Vec<_>>();
+        assert_eq!(stats, [(Some(12), Some(340)), (Some(-3), Some(1204)), (None, None), (None, None)]);
+    }
+
+    #[test]
+    fn unchanged_pages_are_not_parsed_again() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([("/torrent/5558130/friendly-scraper/", Route::ok(PAGE))]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let mut previous = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_ne!(previous.page_hash, 0);
+        // Only kept if the page is skipped, as parsing it again would restore the name
+        previous.name = String::from("stale");
+
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW + 60), 5558130, Some(&previous)).unwrap().unwrap();
+        assert_eq!(torrent.name, "stale");
+        assert_eq!(torrent.scraped_ts, NOW + 60);
+        assert_eq!(torrent.last_checked_ts, previous.last_checked_ts + 60);
+
+        previous.page_hash ^= 1;
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW + 60), 5558130, Some(&previous)).unwrap().unwrap();
+        assert_eq!(torrent.name, NAME);
+    }
+
+    /// A comment as the comments endpoint returns it
+    fn raw_comment(commentid: u64) -> String {
+        format!(r#"{{"avatar": "", "class": "user", "comment": "comment {commentid}", "commentid": {commentid}, "posted": "1 hour ago", "username": "alice"}}"#)
+    }
+
+    /// The fixture page, claiming `count` comments
+    fn page_with_comments(count: usize) -> String {
+        PAGE.replace("Comments<span class=\"active\">0</span>", &format!("Comments<span class=\"active\">{count}</span>"))
+    }
+
+    #[test]
+    fn comments_come_from_a_custom_path() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page_with_comments(2))),
+            ("/api/comments/5558130?page=1", Route::ok(format!("[{}]", raw_comment(1)))),
+            ("/api/comments/5558130?page=2", Route::ok(format!("[{}]", raw_comment(2)))),
+        ]);
+        let config = Config { base_url: server.url(), comments_path: String::from("/api/comments/{id}?page={page}"), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(torrent.comments.iter().map(|comment| comment.commentid).collect::<Vec<_>>(), [1, 2]);
+        let paths = server.requests().into_iter().map(|request| request.path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/torrent/5558130/friendly-scraper/", "/api/comments/5558130?page=1", "/api/comments/5558130?page=2"]);
+    }
+
+    #[test]
+    fn omitted_comments_are_left_out_of_the_json() {
+        use fixture_server::{FixtureServer, Route};
+
+        let stash_dir = stash::TempStash::new("omit-comments");
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page_with_comments(1))),
+            ("/comments.php?torrentid=5558130", Route::ok(format!("[{}]", raw_comment(1)))),
+        ]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(torrent.comments.len(), 1);
+
+        let mut stash = Stash::open().unwrap().with_omitted_fields(vec![OptionalField::Comments]);
+        stash.insert(5558130, Some(torrent), NOW).unwrap();
+        stash.save().unwrap();
+        let chunk: serde_json::Value = serde_json::from_slice(&std::fs::read(stash_dir.path().join("5558.json")).unwrap()).unwrap();
+        let record = chunk["5558130"].as_object().unwrap();
+        assert!(record.contains_key("name"));
+        assert!(!record.contains_key("comments"));
+        assert_eq!(record["comment_count"], 1);
+    }
+
+    #[test]
+    fn raw_comments_are_kept_in_a_sidecar_file() {
+        use fixture_server::{FixtureServer, Route};
+
+        let stash_dir = stash::TempStash::new("raw-comments");
+        let comments = format!("[{}]", raw_comment(1));
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page_with_comments(1))),
+            ("/comments.php?torrentid=5558130", Route::ok(comments.clone())),
+        ]);
+        let sidecar = stash_dir.path().join("comments/5558130.json");
+        let config = Config { base_url: server.url(), ..Config::default() };
+        scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert!(!sidecar.exists());
+
+        let config = Config { keep_raw_comments: true, ..config };
+        scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(std::fs::read_to_string(sidecar).unwrap(), comments);
+    }
+
+    #[test]
+    fn recent_comments_are_reused() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page_with_comments(1))),
+            ("/comments.php?torrentid=5558130", Route::ok(format!("[{}]", raw_comment(1)))),
+        ]);
+        let config = Config { base_url: server.url(), comments_refresh_days: Some(7), ..Config::default() };
+        let mut previous = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        // Parsed again rather than skipped as unchanged
+        previous.page_hash ^= 1;
+        let comment_requests = || server.requests().iter().filter(|request| request.path.starts_with("/comments.php")).count();
+        assert_eq!(comment_requests(), 1);
+
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW + 6 * 86400), 5558130, Some(&previous)).unwrap().unwrap();
+        assert_eq!(comment_requests(), 1);
+        assert_eq!(torrent.comments.len(), 1);
+        assert_eq!(torrent.comments_scraped_ts, NOW);
+
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW + 7 * 86400), 5558130, Some(&previous)).unwrap().unwrap();
+        assert_eq!(comment_requests(), 2);
+        assert_eq!(torrent.comments_scraped_ts, NOW + 7 * 86400);
+    }
+
+    #[test]
+    fn not_modified_pages_keep_the_previous_record() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([("/torrent/5558130/friendly-scraper/", Route::status(304, ""))]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let mut previous = parse_page(PAGE);
+        previous.name = String::from("previous");
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW + 60), 5558130, Some(&previous)).unwrap().unwrap();
+        assert_eq!(torrent.name, "previous");
+        assert_eq!(torrent.scraped_ts, NOW + 60);
+        assert_eq!(server.requests()[0].header("if-modified-since"), http::http_date(NOW).as_deref());
+    }
+
+    #[test]
+    fn timings_are_recorded_on_demand() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([("/torrent/5558130/friendly-scraper/", Route::ok(PAGE))]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(torrent.scrape_duration_ms, None);
+        assert!(!serde_json::to_string(&torrent).unwrap().contains("scrape_duration_ms"));
+
+        let config = Config { record_timings: true, ..config };
+        let start = Instant::now();
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        let elapsed_ms = start.elapsed().as_millis() as u64;
+        assert!(torrent.scrape_duration_ms.is_some_and(|ms| ms <= elapsed_ms), "{:?} > {elapsed_ms}", torrent.scrape_duration_ms);
+    }
+
+    #[test]
+    fn uploader_urls_are_absolute() {
+        use fixture_server::{FixtureServer, Route};
+
+        assert_eq!(parse_page(PAGE).uploader_url.as_deref(), Some("/user/PBDR/"));
+        let server = FixtureServer::start([("/torrent/5558130/friendly-scraper/", Route::ok(PAGE))]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(torrent.uploader_url, Some(format!("{}/user/PBDR/", server.url())));
+
+        assert_eq!(absolute_url("https://1337x.to", "https://x1337x.ws/user/a/"), "https://x1337x.ws/user/a/");
+        assert_eq!(absolute_url("https://1337x.to", "user/a/"), "https://1337x.to/user/a/");
+    }
+
+    #[test]
+    fn comments_are_fetched_when_the_badge_is_missing() {
+        use fixture_server::{FixtureServer, Route};
+
+        let page = PAGE.replace("Comments<span class=\"active\">0</span>", "Comments");
+        assert_ne!(page, PAGE);
+        let server = FixtureServer::start([
+            ("/torrent/1/friendly-scraper/", Route::ok(page.clone())),
+            ("/comments.php?torrentid=1", Route::ok(format!("[{}, {}]", raw_comment(1), raw_comment(2)))),
+            ("/torrent/2/friendly-scraper/", Route::ok(page)),
+        ]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 1, None).unwrap().unwrap();
+        assert!(torrent.comment_count_unknown);
+        assert_eq!((torrent.comment_count, torrent.comments.len()), (2, 2));
+
+        // Without comments, the endpoint may not answer with a list, which isn't worth a warning
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 2, None).unwrap().unwrap();
+        assert_eq!((torrent.comment_count, torrent.comments.len()), (0, 0));
+        assert!(torrent.warnings.is_empty(), "{:?}", torrent.warnings);
+        assert_eq!(server.requests().last().unwrap().path, "/comments.php?torrentid=2");
+    }
+
+    #[test]
+    fn failed_comment_pages_keep_earlier_ones() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page_with_comments(3))),
+            ("/comments.php?torrentid=5558130&page=1", Route::ok(format!("[{}, {}]", raw_comment(1), raw_comment(2)))),
+            ("/comments.php?torrentid=5558130&page=2", Route::status(503, "Service Unavailable")),
+        ]);
+        let config = Config { base_url: server.url(), retries: 0, comments_path: String::from("/comments.php?torrentid={id}&page={page}"), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(torrent.comments.len(), 2);
+        assert!(torrent.comments_incomplete);
+        assert!(torrent.is_missing(RecordPart::Comments));
+        assert_eq!(torrent.warnings, ["Comments incomplete: page 2 of 5558130 failed with status code 503"]);
+    }
+
+    #[test]
+    fn overlapping_comment_pages_keep_one_copy() {
+        use fixture_server::{FixtureServer, Route};
+
+        let repeated = raw_comment(2).replace("comment 2", "comment 2, edited");
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page_with_comments(3))),
+            ("/comments.php?torrentid=5558130&page=1", Route::ok(format!("[{}, {}]", raw_comment(1), raw_comment(2)))),
+            ("/comments.php?torrentid=5558130&page=2", Route::ok(format!("[{repeated}, {}]", raw_comment(3)))),
+        ]);
+        let config = Config { base_url: server.url(), retries: 0, comments_path: String::from("/comments.php?torrentid={id}&page={page}"), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(torrent.comments.iter().map(|comment| comment.commentid).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(torrent.comments[1].comment, "comment 2");
+        assert!(!torrent.is_missing(RecordPart::Comments));
+    }
+
+    #[test]
+    fn reads_comment_edit_times() {
+        use fixture_server::{FixtureServer, Route};
+
+        let edited = |commentid: u64, edited: &str| raw_comment(commentid).replace("\"posted\"", &format!("\"edited\": {edited}, \"posted\""));
+        let comments = format!("[{}, {}, {}, {}, {}]", raw_comment(1), edited(2, "\"10 minutes ago\""), edited(3, "\"\""), edited(4, "null"), edited(5, "\"whenever\""));
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page_with_comments(5))),
+            ("/comments.php?torrentid=5558130", Route::ok(comments)),
+        ]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        let edits = torrent.comments.iter().map(|comment| comment.edited).collect::<Vec<_>>();
+        assert_eq!(edits, [None, Some(NOW - 600), None, None, None]);
+        assert_eq!(torrent.comments[1].posted, NOW - 3600);
+        assert_eq!(torrent.warnings, ["Failed to parse comment edit time: whenever"]);
+    }
+
+    #[test]
+    fn files_come_from_their_endpoint_when_left_out_of_the_page() {
+        use fixture_server::{FixtureServer, Route};
+
+        let start = PAGE.find("<h2>Files: </h2>").unwrap();
+        let end = start + PAGE[start..].find("</ul>").unwrap();
+        let page = format!("{}<h2>Files: 2</h2><ul>{}", &PAGE[..start], &PAGE[end..]);
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page.clone())),
+            ("/files.php?torrentid=5558130", Route::ok("<ul><li>movie.mkv (1.2 GB)</li><li>movie.srt (80 KB)</li></ul>")),
+        ]);
+        let config = Config { base_url: server.url(), retries: 0, ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(torrent.files.iter().map(|file| file.name.as_str()).collect::<Vec<_>>(), ["movie.mkv", "movie.srt"]);
+        assert!(!torrent.is_missing(RecordPart::Files));
+        assert!(torrent.warnings.is_empty());
+
+        // Files listed on the page are not fetched again
+        let server = FixtureServer::start([("/torrent/5558130/friendly-scraper/", Route::ok(PAGE))]);
+        let config = Config { base_url: server.url(), retries: 0, ..Config::default() };
+        scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert_eq!(server.requests().len(), 1);
+
+        let server = FixtureServer::start([("/torrent/5558130/friendly-scraper/", Route::ok(page))]);
+        let config = Config { base_url: server.url(), retries: 0, ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert!(torrent.files.is_empty());
+        assert!(torrent.is_missing(RecordPart::Files));
+        assert_eq!(torrent.warnings, ["Unexpected status code for files: 404"]);
+    }
+
+    #[test]
+    fn failed_comments_are_recorded_as_warnings() {
+        use fixture_server::{FixtureServer, Route};
+
+        let page = PAGE.replace("Comments<span class=\"active\">0</span>", "Comments<span class=\"active\">3</span>");
+        let server = FixtureServer::start([
+            ("/torrent/5558130/friendly-scraper/", Route::ok(page)),
+            ("/comments.php?torrentid=5558130", Route::status(503, "Service Unavailable")),
+        ]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 5558130, None).unwrap().unwrap();
+        assert!(torrent.comments.is_empty());
+        assert_eq!(torrent.warnings, ["Unexpected status code for comments: 503"]);
+    }
+
+    #[test]
+    fn shed_pages_are_retried_out_of_the_budget() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([("/torrent/7/friendly-scraper/", Route::ok("busy"))]);
+        let config = Config { base_url: server.url(), retries: 3, ..Config::default() };
+        let client = MinreqClient { settings: ClientSettings { retry_budget: Some(AtomicU64::new(0)), ..ClientSettings::default() } };
+        let err = scrape_torrent(&config, &client, &FixedClock(NOW), 7, None).unwrap_err();
+        assert!(err.to_string().contains("shedding load"), "{err}");
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_structure_of_descriptions() {
+        let html = Html::parse_fragment(concat!(
+            "<div id=\"d\"><p>First   paragraph\n continued<br>next line</p>",
+            "<script>ignored()</script><ul><li>one</li><li> </li><li>two</li></ul>",
+            "<table><tr><td>Video</td> <td>H.265</td></tr><tr><td>Audio</td> <td>DDP5.1</td></tr></table></div>",
+        ));
+        let selector = Selector::parse("#d").unwrap();
+        let text = structured_text(html.select(&selector).next().unwrap());
+        assert_eq!(text, "First paragraph continued\nnext line\n\n- one\n\n- two\n\nVideo H.265\n\nAudio DDP5.1");
+    }
+
+    fn flag(html: &str) -> Option<String> {
+        flag_language(Html::parse_fragment(html).root_element())
+    }
+
+    #[test]
+    fn reads_language_from_flags() {
+        assert_eq!(flag(r#"<img alt="flag" src="/images/flags/gb.png">"#).as_deref(), Some("English"));
+        assert_eq!(flag(r#"<img alt="french" src="/images/flags/unknown.png">"#).as_deref(), Some("French"));
+        assert_eq!(flag(r#"<img title="de" src="/images/flag.png">"#).as_deref(), Some("German"));
+        assert_eq!(flag(r#"<img alt="Country flag" title="Japanese">"#).as_deref(), Some("Japanese"));
+        assert_eq!(flag(r#"<img alt="Klingon" src="/images/flags/xx.png">"#), None);
+        assert_eq!(flag("<span>English</span>"), None);
+    }
+
+    #[test]
+    fn codes_map_to_known_languages() {
+        for code in ["gb", "us", "fr", "de", "mx", "it", "br", "nl", "ru", "pl", "tr", "se", "jp", "kr", "cn", "in", "sa"] {
+            assert!(LANGUAGES.contains(&language_of_code(code).unwrap()), "{code}");
+        }
+    }
+
+    #[test]
+    fn classifies_files_by_name() {
+        assert_eq!(FileKind::of("movie.sample.mkv"), FileKind::Sample);
+        assert_eq!(FileKind::of("Sample/movie.mkv"), FileKind::Sample);
+        assert_eq!(FileKind::of("subs.srt"), FileKind::Subtitle);
+        assert_eq!(FileKind::of("readme.nfo"), FileKind::Nfo);
+        assert_eq!(FileKind::of("movie.mkv"), FileKind::Main);
+        assert_eq!(FileKind::of("examples.txt"), FileKind::Other);
+    }
+
+    #[test]
+    fn leaves_unclassified_files_out() {
+        let file = |name: &str| serde_json::to_string(&parse_file(&format!("{name} (1.0 MB)")).unwrap()).unwrap();
+        assert_eq!(file("movie.mkv"), r#"{"name":"movie.mkv","size":1048576,"kind":"main"}"#);
+        assert_eq!(file("cover.jpg"), r#"{"name":"cover.jpg","size":1048576}"#);
+        let parsed: File = serde_json::from_str(r#"{"name":"cover.jpg","size":1048576}"#).unwrap();
+        assert_eq!(parsed.kind, FileKind::Other);
+    }
+
+    #[test]
+    fn built_magnets_keep_the_tracker_order() {
+        let mut torrent = parse_page(PAGE);
+        torrent.page_magnet = None;
+        torrent.web_seeds.clear();
+        torrent.trackers = ["http://b.example/announce", "udp://a.example:80", "http://b.example/announce"].map(String::from).to_vec();
+        assert_eq!(
+            torrent.build_magnet_link(),
+            format!("magnet:?xt=urn:btih:4738fb09f6ae3e2f45bca0ffec9f32779500ab03&dn={NAME}&tr=http%3A%2F%2Fb.example%2Fannounce&tr=udp%3A%2F%2Fa.example%3A80"),
+        );
+    }
+
+    #[test]
+    fn young_torrents_are_refreshed_sooner() {
+        let mut torrent = parse_page(PAGE);
+        let next_refresh = |torrent: &mut TorrentInfo, age: u64| {
+            torrent.uploaded_ts = NOW - age;
+            torrent.schedule_refresh();
+            torrent.next_refresh_ts - NOW
+        };
+        assert_eq!(next_refresh(&mut torrent, 3600), 86400);
+        assert_eq!(next_refresh(&mut torrent, 86400 * 7 - 1), 86400);
+        assert_eq!(next_refresh(&mut torrent, 86400 * 7), 86400 * 7);
+        assert_eq!(next_refresh(&mut torrent, 86400 * 30), 86400 * 30);
+        assert_eq!(next_refresh(&mut torrent, 86400 * 3650), 86400 * 30);
+    }
+
+    #[test]
+    fn reindex_classifies_files() {
+        let mut torrent = parse_page(PAGE);
+        torrent.reindex();
+        assert!(!torrent.reindex());
+
+        assert!(!torrent.files.is_empty());
+        let kinds = torrent.files.iter().map(|file| file.kind).collect::<Vec<_>>();
+        torrent.files.iter_mut().for_each(|file| file.kind = FileKind::Other);
+        assert!(torrent.reindex());
+        assert_eq!(torrent.files.iter().map(|file| file.kind).collect::<Vec<_>>(), kinds);
+        assert!(kinds.contains(&FileKind::Main));
+    }
+
+    #[test]
+    fn stores_only_selected_categories() {
+        let config = Config { only_categories: Some(vec![Category::Movies, Category::Tv]), ..Config::default() };
+        let mut torrent = parse_page(PAGE);
+        torrent.category = String::from("Movies");
+        assert_eq!(rejection_reason(&config, &torrent), None);
+        torrent.category = String::from("TV");
+        assert_eq!(rejection_reason(&config, &torrent), None);
+        torrent.category = String::from("Music");
+        assert_eq!(rejection_reason(&config, &torrent).as_deref(), Some("category Music is not wanted"));
+    }
+
+    #[test]
+    fn flags_and_excludes_adult_torrents() {
+        let mut torrent = parse_page(PAGE);
+        assert!(!torrent.is_adult());
+        torrent.category = String::from("XXX");
+        assert!(torrent.is_adult());
+        assert_eq!(rejection_reason(&Config::default(), &torrent), None);
+        let config = Config { exclude_nsfw: true, ..Config::default() };
+        assert_eq!(rejection_reason(&config, &torrent).as_deref(), Some("adult content is excluded"));
+    }
+
+    #[test]
+    fn music_torrents_skip_images_per_category() {
+        use fixture_server::{FixtureServer, Route};
+
+        let music = PAGE.replace("<strong>Category</strong> <span>Movies</span>", "<strong>Category</strong> <span>Music</span>");
+        assert_ne!(music, PAGE);
+        let server = FixtureServer::start([
+            ("/torrent/1/friendly-scraper/", Route::ok(music)),
+            ("/torrent/2/friendly-scraper/", Route::ok(PAGE)),
+        ]);
+        let mut config = Config { base_url: server.url(), ..Config::default() };
+        config.category_omit_fields.insert(Category::Music, vec![OptionalField::Images]);
+
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 1, None).unwrap().unwrap();
+        assert_eq!(torrent.normalized_category(), Category::Music);
+        assert!(torrent.images.is_empty());
+        assert!(!torrent.description.is_empty());
+        let torrent = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 2, None).unwrap().unwrap();
+        assert!(!torrent.images.is_empty());
+    }
+
+    #[test]
+    fn delays_stay_in_the_jitter_range() {
+        let config = Config { delay_ms: 100, jitter_ms: 50, seed: Some(7), ..Config::default() };
+        let delays = |config: &Config| {
+            let mut rng = delay_rng(config);
+            (0..1000).map(|_| inter_request_delay(config, &mut rng)).collect::<Vec<_>>()
+        };
+        let first = delays(&config);
+        assert!(first.iter().all(|delay| (Duration::from_millis(100)..=Duration::from_millis(150)).contains(delay)));
+        assert!(first.contains(&Duration::from_millis(100)) && first.contains(&Duration::from_millis(150)));
+        assert_eq!(delays(&config), first);
+        assert!(delays(&Config { jitter_ms: 0, ..config }).iter().all(|delay| *delay == Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn rejects_torrents_below_the_minimum_size() {
+        let config = Config { min_size: parse_data_size("1 GB"), ..Config::default() };
+        assert_eq!(config.min_size, Some(1 << 30));
+        let mut torrent = parse_page(PAGE);
+        assert_eq!(rejection_reason(&config, &torrent), None);
+        torrent.total_size = ByteSize::from(1 << 30);
+        assert_eq!(rejection_reason(&config, &torrent), None);
+        torrent.total_size = ByteSize::from((1 << 30) - 1);
+        assert!(rejection_reason(&config, &torrent).is_some_and(|reason| reason.contains("below the minimum")));
+    }
+
+    #[test]
+    fn checks_free_disk_space() {
+        use disk::FixedDisk;
+
+        let config = Config { min_free_disk: Some(1 << 30), ..Config::default() };
+        assert_eq!(low_disk_space(&config, &FixedDisk(1 << 20)), Some(1 << 20));
+        assert_eq!(low_disk_space(&config, &FixedDisk(2 << 30)), None);
+        assert_eq!(low_disk_space(&Config::default(), &FixedDisk(0)), None);
+    }
+
+    #[test]
+    fn refill_scrapes_only_incomplete_torrents() {
+        use fixture_server::{FixtureServer, Route};
+
+        let _stash = stash::TempStash::new("refill");
+        let server = FixtureServer::start([
+            ("/torrent/10/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/11/friendly-scraper/", Route::ok(PAGE)),
+        ]);
+        let mut stash = Stash::open().unwrap();
+        let mut incomplete = parse_page(PAGE);
+        incomplete.files.clear();
+        stash.insert(10, Some(incomplete), NOW).unwrap();
+        stash.insert(11, Some(parse_page(PAGE)), NOW).unwrap();
+        stash.save().unwrap();
+
+        let config = Config { base_url: server.url(), missing: vec![RecordPart::Files], delay_ms: 0, ..Config::default() };
+        refill(&config, &fixture_client(), &FixedClock(NOW)).unwrap();
+        let paths = server.requests().into_iter().map(|request| request.path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/torrent/10/friendly-scraper/"]);
+        assert_eq!(Stash::read_torrent(10).unwrap().unwrap().files.len(), 1);
+
+        assert!(refill(&Config::default(), &fixture_client(), &FixedClock(NOW)).is_err());
+    }
+
+    #[test]
+    fn scans_stop_after_count_ids() {
+        use fixture_server::{FixtureServer, Route};
+
+        let _stash = stash::TempStash::new("count");
+        let server = FixtureServer::start([
+            ("/torrent/100/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/101/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/102/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/103/friendly-scraper/", Route::ok(PAGE)),
+        ]);
+        let config = Config { base_url: server.url(), start_id: 100, count: Some(3), delay_ms: 0, ..Config::default() };
+        scan(&config, &fixture_client(), &FixedClock(NOW), &disk::FixedDisk(u64::MAX)).unwrap();
+
+        let paths = server.requests().into_iter().map(|request| request.path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/torrent/100/friendly-scraper/", "/torrent/101/friendly-scraper/", "/torrent/102/friendly-scraper/"]);
+        assert_eq!(Stash::count_present().unwrap(), 3);
+        assert_eq!(Stash::read_cursor().unwrap(), Some(103));
+    }
+
+    #[test]
+    fn scans_skip_excluded_ids() {
+        use fixture_server::{FixtureServer, Route};
+
+        let _stash = stash::TempStash::new("exclude-ids");
+        let server = FixtureServer::start([
+            ("/torrent/100/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/101/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/102/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/103/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/104/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/105/friendly-scraper/", Route::ok(PAGE)),
+        ]);
+        let config = Config {
+            base_url: server.url(),
+            start_id: 100,
+            count: Some(3),
+            delay_ms: 0,
+            exclude_ids: vec![101..=101, 103..=104],
+            ..Config::default()
+        };
+        scan(&config, &fixture_client(), &FixedClock(NOW), &disk::FixedDisk(u64::MAX)).unwrap();
+
+        let paths = server.requests().into_iter().map(|request| request.path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/torrent/100/friendly-scraper/", "/torrent/102/friendly-scraper/", "/torrent/105/friendly-scraper/"]);
+        // Excluded ids are not even recorded as missing
+        let mut stash = Stash::open().unwrap();
+        for id in [101, 103, 104] {
+            assert!(!stash.contains_key(&id).unwrap());
+        }
+    }
+
+    #[test]
+    fn strict_scans_discard_broken_records() {
+        use fixture_server::{FixtureServer, Route};
+
+        let page = PAGE.replace("<strong>Total size</strong> <span>1.3 GB</span>", "<strong>Total size</strong> <span>0 B</span>");
+        assert_ne!(page, PAGE);
+        assert_eq!(parse_page(&page).problems(), ["zero size while files add up to 1.3 GB"]);
+        for strict in [true, false] {
+            let _stash = stash::TempStash::new("strict");
+            let server = FixtureServer::start([("/torrent/100/friendly-scraper/", Route::ok(page.clone()))]);
+            let config = Config { base_url: server.url(), start_id: 100, count: Some(1), delay_ms: 0, strict, ..Config::default() };
+            scan(&config, &fixture_client(), &FixedClock(NOW), &disk::FixedDisk(u64::MAX)).unwrap();
+            assert_eq!(Stash::open().unwrap().contains_key(&100).unwrap(), !strict);
+        }
+    }
+
+    #[test]
+    fn ignoring_the_cursor_starts_at_start_id() {
+        use fixture_server::FixtureServer;
+
+        let _stash = stash::TempStash::new("ignore-cursor");
+        for (resume_from_cursor, first_path) in [(false, "/torrent/100/friendly-scraper/"), (true, "/torrent/200/friendly-scraper/")] {
+            Stash::write_cursor(200).unwrap();
+            let server = FixtureServer::start([]);
+            let config = Config { base_url: server.url(), start_id: 100, count: Some(1), retries: 0, delay_ms: 0, resume_from_cursor, ..Config::default() };
+            scan(&config, &fixture_client(), &FixedClock(NOW), &disk::FixedDisk(u64::MAX)).unwrap();
+            assert_eq!(server.requests()[0].path, first_path);
+        }
+    }
+
+    #[test]
+    fn second_incremental_scan_starts_above_the_stored_id() {
+        use fixture_server::{FixtureServer, Route};
+
+        let stash_dir = stash::TempStash::new("since-id");
+        let since_id_file = stash_dir.path().join("since-id");
+        let server = FixtureServer::start([
+            ("/torrent/100/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/101/friendly-scraper/", Route::ok(PAGE)),
+        ]);
+        let config = Config {
+            base_url: server.url(),
+            start_id: 100,
+            count: Some(3),
+            retries: 0,
+            delay_ms: 0,
+            since_id_file: Some(since_id_file.clone()),
+            ..Config::default()
+        };
+        scan(&config, &fixture_client(), &FixedClock(NOW), &disk::FixedDisk(u64::MAX)).unwrap();
+        assert_eq!(std::fs::read_to_string(&since_id_file).unwrap(), "101");
+
+        // New torrents showed up above the highest one found so far
+        let server = FixtureServer::start([
+            ("/torrent/102/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/103/friendly-scraper/", Route::ok(PAGE)),
+        ]);
+        let config = Config { base_url: server.url(), count: Some(2), ..config };
+        scan(&config, &fixture_client(), &FixedClock(NOW), &disk::FixedDisk(u64::MAX)).unwrap();
+        let paths = server.requests().into_iter().map(|request| request.path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/torrent/102/friendly-scraper/", "/torrent/103/friendly-scraper/"]);
+        assert_eq!(std::fs::read_to_string(&since_id_file).unwrap(), "103");
+    }
+
+    #[test]
+    fn ranks_top_torrents_by_seeders() {
+        let _stash = stash::TempStash::new("top");
+        let mut stash = Stash::open().unwrap();
+        for (id, seeders) in [(1, 5), (2, 50), (3, 0), (1001, 20), (1002, 50)] {
+            let mut torrent = parse_page(PAGE);
+            torrent.seeders = seeders;
+            stash.insert(id, Some(torrent), NOW).unwrap();
+        }
+        stash.insert(4, None, NOW).unwrap();
+        stash.save().unwrap();
+
+        let config = Config { by: RankBy::Seeders, limit: 2, ..Config::default() };
+        let entries = top_entries(&config).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.seeders).collect::<Vec<_>>(), [50, 50]);
+        let config = Config { limit: 3, ..config };
+        assert_eq!(top_entries(&config).unwrap().iter().map(|entry| entry.id).collect::<Vec<_>>().last(), Some(&1001));
+    }
+
+    #[test]
+    fn backfills_missing_magnet_links() {
+        let _stash = stash::TempStash::new("backfill");
+        let old = parse_page(PAGE);
+        assert_eq!(old.magnet_link, None);
+        let mut invalid = old.clone();
+        invalid.infohash = InfoHash::raw(String::from("not a hash"));
+        let mut stash = Stash::open().unwrap();
+        stash.insert(1, Some(old), NOW).unwrap();
+        stash.insert(2, Some(invalid), NOW).unwrap();
+        let mut kept = parse_page(PAGE);
+        kept.magnet_link = Some(String::from("magnet:?xt=urn:btih:4738fb09f6ae3e2f45bca0ffec9f32779500ab03"));
+        stash.insert(3, Some(kept.clone()), NOW).unwrap();
+        stash.save().unwrap();
+
+        backfill_magnets().unwrap();
+        let magnet = |id| Stash::read_torrent(id).unwrap().unwrap().magnet_link;
+        let mut expected = parse_page(PAGE);
+        assert!(expected.fill_magnet_link());
+        assert_eq!(magnet(1), expected.magnet_link);
+        assert_eq!(magnet(2), None);
+        assert_eq!(magnet(3), kept.magnet_link);
+    }
+
+    #[test]
+    fn consecutive_errors_checkpoint_the_stash() {
+        use fixture_server::{FixtureServer, Route};
+
+        /// Records how many entries the stash holds on disk every time the scan checks free space, which it does before each id
+        struct SavedEntries(std::cell::RefCell<Vec<usize>>);
+
+        impl DiskSpace for SavedEntries {
+            fn available(&self, _path: &Path) -> Result<u64, anyhow::Error> {
+                self.0.borrow_mut().push(Stash::read_chunk(0).map_or(0, |chunk| chunk.len()));
+                Ok(u64::MAX)
+            }
+        }
+
+        let _stash = stash::TempStash::new("checkpoint");
+        let server = FixtureServer::start([("/torrent/100/friendly-scraper/", Route::ok(PAGE))]);
+        let config = Config {
+            base_url: server.url(),
+            start_id: 100,
+            count: Some(5),
+            retries: 0,
+            delay_ms: 0,
+            checkpoint_on_error: Some(2),
+            min_free_disk: Some(1),
+            ..Config::default()
+        };
+        let saved = SavedEntries(Default::default());
+        scan(&config, &fixture_client(), &FixedClock(NOW), &saved).unwrap();
+        // Ids 101 to 104 fail, so the stash is saved right after 102 rather than at the end only
+        assert_eq!(saved.0.into_inner(), [0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn crawls_from_seeds_to_the_torrents_they_link() {
+        use fixture_server::{FixtureServer, Route};
+
+        let linking = |ids: &[usize]| {
+            let links = ids.iter().map(|id| format!("<a href=\"/torrent/{id}/other/\">{id}</a>")).collect::<String>();
+            PAGE.replace("</body>", &format!("{links}</body>"))
+        };
+        let _stash = stash::TempStash::new("crawl");
+        let server = FixtureServer::start([
+            ("/torrent/100/friendly-scraper/", Route::ok(linking(&[200, 300]))),
+            ("/torrent/200/friendly-scraper/", Route::ok(linking(&[100, 300]))),
+            ("/torrent/300/friendly-scraper/", Route::ok(linking(&[100]))),
+        ]);
+        let config = Config { base_url: server.url(), seed_ids: vec![100, 200], delay_ms: 0, ..Config::default() };
+        crawl(&config, &fixture_client(), &FixedClock(NOW)).unwrap();
+
+        let paths = server.requests().into_iter().map(|request| request.path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/torrent/100/friendly-scraper/", "/torrent/200/friendly-scraper/", "/torrent/300/friendly-scraper/"]);
+        assert_eq!(Stash::count_present().unwrap(), 3);
+    }
+
+    #[test]
+    fn finds_torrents_by_partial_name() {
+        let _stash = stash::TempStash::new("find");
+        let mut stash = Stash::open().unwrap();
+        for (id, name, category) in [(1, "The.Weapon.2023.1080p", "Movies"), (2, "Weapons of Math", "Other"), (1500, "THE WEAPON soundtrack", "Music"), (2001, "Unrelated", "Movies")] {
+            let mut torrent = parse_page(PAGE);
+            torrent.name = String::from(name);
+            torrent.category = String::from(category);
+            stash.insert(id, Some(torrent), NOW).unwrap();
+        }
+        stash.insert(3, None, NOW).unwrap();
+        stash.save().unwrap();
+
+        let found_ids = |config: &Config| {
+            let mut out = Vec::new();
+            find(config, &mut out).unwrap();
+            String::from_utf8(out).unwrap().lines().map(|line| line.split_whitespace().next().unwrap().parse().unwrap()).collect::<Vec<usize>>()
+        };
+        assert_eq!(found_ids(&Config { name: Some(String::from("weapon")), ..Config::default() }), [1, 2, 1500]);
+        assert_eq!(found_ids(&Config { name: Some(String::from("the weapon")), ..Config::default() }), [1500]);
+        assert_eq!(found_ids(&Config { name: Some(String::from("Weapon")), only_categories: Some(vec![Category::Music]), ..Config::default() }), [1500]);
+        assert!(find(&Config::default(), std::io::sink()).is_err());
+    }
+
+    #[test]
+    fn probes_a_half_live_range() {
+        use fixture_server::{FixtureServer, Route};
+
+        let server = FixtureServer::start([
+            ("/torrent/100/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/102/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/104/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/106/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/108/friendly-scraper/", Route::ok(PAGE)),
+            ("/torrent/109/friendly-scraper/", Route::status(500, "Internal Server Error")),
+        ]);
+        let config = Config { base_url: server.url(), start_id: 100, end_id: Some(109), samples: 100, retries: 0, delay_ms: 0, ..Config::default() };
+        let counts = probe_sample(&config, &fixture_client(), &FixedClock(NOW)).unwrap();
+        assert_eq!((counts.range_len, counts.probed, counts.errors, counts.live), (10, 9, 1, 5));
+        assert_eq!(server.requests().len(), 10);
+
+        assert!(probe_sample(&Config { end_id: Some(99), ..config }, &fixture_client(), &FixedClock(NOW)).is_err());
+    }
+
+    #[test]
+    fn scans_pause_outside_active_hours() {
+        use clock::SteppingClock;
+        use fixture_server::{FixtureServer, Route};
+
+        let _stash = stash::TempStash::new("active-hours");
+        let server = FixtureServer::start([("/torrent/5558130/friendly-scraper/", Route::ok(PAGE))]);
+        let config = Config {
+            base_url: server.url(),
+            start_id: 5558130,
+            count: Some(1),
+            delay_ms: 0,
+            active_hours: Some("01:00-02:00 UTC".parse().unwrap()),
+            ..Config::default()
+        };
+        // 2023-11-14T01:00:00Z, the clock starting ten minutes before
+        let opening = 1_699_923_600;
+        let clock = SteppingClock { now: AtomicU64::new(opening - 600), step: 30 };
+        scan(&config, &fixture_client(), &clock, &disk::FixedDisk(u64::MAX)).unwrap();
+
+        assert_eq!(server.requests().len(), 1);
+        let torrent = Stash::read_torrent(5558130).unwrap().unwrap();
+        assert!((opening..opening + 3600).contains(&torrent.scraped_ts), "{}", torrent.scraped_ts);
+        assert_eq!(Stash::read_cursor().unwrap(), Some(5558131));
+    }
+
+    #[test]
+    fn cursor_stays_at_torrents_being_processed() {
+        assert_eq!(scan_cursor(120, &VecDeque::new()), 120);
+        assert_eq!(scan_cursor(120, &VecDeque::from([(NOW, 104, 1), (NOW, 101, 2), (NOW, 110, 1)])), 101);
+    }
+
+    #[test]
+    fn replays_torrents_left_being_processed() {
+        let log = format!("[2026-10-16T00:00:00Z ERROR x1337x_scraper] Failed to scrape torrent 104: {BeingProcessed}\n[2026-10-16T00:00:01Z INFO  x1337x_scraper] Scraped 3 torrents\n");
+        assert_eq!(failed_ids_in_log(&log), BTreeSet::from([104]));
+    }
+
+    #[test]
+    fn missing_torrents_are_not_errors() {
+        use fixture_server::{FixtureServer, Route};
+
+        // As small as the error pages of the site, below --min-page-bytes
+        let page = concat!(
+            "<!DOCTYPE html><html><head><title>Error something went wrong | 1337x</title></head><body>",
+            "<div class=\"box-info\"><div class=\"box-info-heading\"><h1>Error something went wrong</h1></div>",
+            "<div class=\"box-info-detail\"><p>Bad Torrent ID.</p></div></div></body></html>",
+        );
+        let server = FixtureServer::start([("/torrent/42/friendly-scraper/", Route::ok(page))]);
+        let config = Config { base_url: server.url(), retries: 3, ..Config::default() };
+        assert!((page.len() as u64) < config.min_page_bytes);
+        assert!(scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 42, None).unwrap().is_none());
+        assert_eq!(server.requests().len(), 1);
+
+        let server = FixtureServer::start([]);
+        let config = Config { base_url: server.url(), ..Config::default() };
+        let err = scrape_torrent(&config, &fixture_client(), &FixedClock(NOW), 42, None).unwrap_err();
+        assert!(err.to_string().contains("404"), "{err}");
+    }
+}
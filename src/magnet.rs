@@ -0,0 +1,119 @@
+/// Percent-encodes everything but unreserved characters (RFC 3986)
+pub fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Builds a magnet link from its parts, web seeds going in `ws` parameters (BEP 19)
+pub fn build(infohash: &str, name: &str, trackers: &[&str], web_seeds: &[String]) -> String {
+    let mut magnet = format!("magnet:?xt=urn:btih:{infohash}");
+    if !name.is_empty() {
+        magnet.push_str("&dn=");
+        magnet.push_str(&url_encode(name));
+    }
+    for tracker in trackers {
+        magnet.push_str("&tr=");
+        magnet.push_str(&url_encode(tracker));
+    }
+    for web_seed in web_seeds {
+        magnet.push_str("&ws=");
+        magnet.push_str(&url_encode(web_seed));
+    }
+    magnet
+}
+
+/// Checks that a magnet link found on a page refers to the expected infohash
+pub fn is_valid_for(magnet: &str, infohash: &str) -> bool {
+    let Some(params) = magnet.strip_prefix("magnet:?") else {
+        return false;
+    };
+    !infohash.is_empty() && params.split('&').any(|param| {
+        param.get(..12).is_some_and(|key| key.eq_ignore_ascii_case("xt=urn:btih:")) && param[12..].eq_ignore_ascii_case(infohash)
+    })
+}
+
+/// Lists what makes a magnet link unfit for publishing: a missing or malformed btih, a missing display name, or badly encoded parameters
+pub fn problems(magnet: &str) -> Vec<String> {
+    let Some(params) = magnet.strip_prefix("magnet:?") else {
+        return vec![String::from("missing magnet:? prefix")];
+    };
+    let mut problems = Vec::new();
+    let mut has_name = false;
+    let mut btih = None;
+    for param in params.split('&') {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        match key {
+            "xt" => btih = btih.or(value.strip_prefix("urn:btih:")),
+            "dn" => has_name = has_name || !value.is_empty(),
+            _ => (),
+        }
+        if !is_url_encoded(value) {
+            problems.push(format!("badly encoded {key} parameter"));
+        }
+    }
+    match btih {
+        Some(btih) if btih.len() == 40 && btih.bytes().all(|b| b.is_ascii_hexdigit()) => (),
+        // Base32 btih, as older clients use
+        Some(btih) if btih.len() == 32 && btih.bytes().all(|b| matches!(b, b'A'..=b'Z' | b'a'..=b'z' | b'2'..=b'7')) => (),
+        Some(btih) => problems.push(format!("btih of invalid length {}", btih.len())),
+        None => problems.push(String::from("missing btih")),
+    }
+    if !has_name {
+        problems.push(String::from("missing display name"));
+    }
+    problems
+}
+
+/// Checks that a query value only holds characters allowed in URLs, with complete percent escapes
+fn is_url_encoded(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if bytes.get(i + 1..i + 3).is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)) => i += 3,
+            b'%' | b'#' | b'&' => return false,
+            byte if byte.is_ascii_graphic() => i += 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn builds_encoded_links() {
+        assert_eq!(url_encode("a b/c~"), "a%20b%2Fc~");
+        let magnet = build(HASH, "Some Name", &["udp://tracker.example:80/announce"], &[String::from("https://seed.example/f")]);
+        assert_eq!(magnet, format!("magnet:?xt=urn:btih:{HASH}&dn=Some%20Name&tr=udp%3A%2F%2Ftracker.example%3A80%2Fannounce&ws=https%3A%2F%2Fseed.example%2Ff"));
+        assert_eq!(build(HASH, "", &[], &[]), format!("magnet:?xt=urn:btih:{HASH}"));
+    }
+
+    #[test]
+    fn checks_the_infohash_of_page_links() {
+        assert!(is_valid_for(&format!("magnet:?dn=x&XT=URN:BTIH:{}", HASH.to_ascii_uppercase()), HASH));
+        assert!(!is_valid_for(&format!("magnet:?xt=urn:btih:{HASH}"), &HASH.replace('0', "f")));
+        assert!(!is_valid_for("magnet:?xt=urn:btih:", ""));
+        assert!(!is_valid_for(&format!("http://example.com/?xt=urn:btih:{HASH}"), HASH));
+    }
+
+    #[test]
+    fn reports_problems() {
+        assert!(problems(&build(HASH, "Some Name", &["udp://tracker.example:80"], &[])).is_empty());
+        assert!(problems("magnet:?xt=urn:btih:ABCDEFGHIJKLMNOPQRSTUVWXYZ234567&dn=x").is_empty());
+        assert_eq!(problems("http://example.com"), ["missing magnet:? prefix"]);
+        assert_eq!(problems("magnet:?xt=urn:btih:0123&dn="), ["btih of invalid length 4", "missing display name"]);
+        assert_eq!(problems(&format!("magnet:?xt=urn:btih:{HASH}&dn=a b&tr=%zz")), ["badly encoded dn parameter", "badly encoded tr parameter"]);
+        assert_eq!(problems("magnet:?dn=x"), ["missing btih"]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::{io::{Read, Write}, net::TcpListener, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use log::*;
+
+/// Upper bounds (in seconds) of the request duration histogram buckets
+const DURATION_BUCKETS: [f64; 7] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0];
+
+/// Value of `retries_remaining` when retries aren't limited, which leaves the gauge out
+pub const UNLIMITED_RETRIES: u64 = u64::MAX;
+
+pub static METRICS: Metrics = Metrics::new();
+
+pub struct Metrics {
+    pub requests: AtomicU64,
+    pub request_errors: AtomicU64,
+    pub retries: AtomicU64,
+    /// Retries that didn't happen because the retry budget was exhausted
+    pub retries_denied: AtomicU64,
+    /// Retries left in the budget of the run, `UNLIMITED_RETRIES` without a budget
+    pub retries_remaining: AtomicU64,
+    pub request_duration: Histogram,
+}
+
+/// A fixed-bucket histogram that can be updated concurrently
+pub struct Histogram {
+    /// Non-cumulative counts, the last one being the +Inf bucket
+    buckets: [AtomicU64; DURATION_BUCKETS.len() + 1],
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; DURATION_BUCKETS.len() + 1],
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let index = DURATION_BUCKETS.iter().position(|le| seconds <= *le).unwrap_or(DURATION_BUCKETS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str, output: &mut String) {
+        output.push_str(&format!("# TYPE {name} histogram\n"));
+        let mut cumulative = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = DURATION_BUCKETS.get(i).map(|le| le.to_string()).unwrap_or_else(|| String::from("+Inf"));
+            output.push_str(&format!("{name}_bucket{{le=\"{le}\"}} {cumulative}\n"));
+        }
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        output.push_str(&format!("{name}_sum {sum}\n"));
+        output.push_str(&format!("{name}_count {cumulative}\n"));
+    }
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            requests: AtomicU64::new(0),
+            request_errors: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            retries_denied: AtomicU64::new(0),
+            retries_remaining: AtomicU64::new(UNLIMITED_RETRIES),
+            request_duration: Histogram::new(),
+        }
+    }
+
+    /// Renders all metrics in the OpenMetrics text format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        output.push_str("# TYPE scraper_requests counter\n");
+        output.push_str(&format!("scraper_requests_total {}\n", self.requests.load(Ordering::Relaxed)));
+        output.push_str("# TYPE scraper_request_errors counter\n");
+        output.push_str(&format!("scraper_request_errors_total {}\n", self.request_errors.load(Ordering::Relaxed)));
+        output.push_str("# TYPE scraper_retries counter\n");
+        output.push_str(&format!("scraper_retries_total {}\n", self.retries.load(Ordering::Relaxed)));
+        output.push_str("# TYPE scraper_retries_denied counter\n");
+        output.push_str(&format!("scraper_retries_denied_total {}\n", self.retries_denied.load(Ordering::Relaxed)));
+        let retries_remaining = self.retries_remaining.load(Ordering::Relaxed);
+        if retries_remaining != UNLIMITED_RETRIES {
+            output.push_str("# TYPE scraper_retries_remaining gauge\n");
+            output.push_str(&format!("scraper_retries_remaining {retries_remaining}\n"));
+        }
+        self.request_duration.render("scraper_request_duration_seconds", &mut output);
+        output.push_str("# EOF\n");
+        output
+    }
+}
+
+/// Serves the metrics over HTTP on a background thread
+pub fn serve(addr: &str) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(addr)?;
+    info!("Serving metrics on http://{addr}/metrics");
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Failed to accept metrics connection: {err}");
+                    continue;
+                }
+            };
+
+            // We don't care about the request, every path gets the metrics
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer);
+
+            let body = METRICS.render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/openmetrics-text; version=1.0.0; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            if let Err(err) = stream.write_all(response.as_bytes()) {
+                warn!("Failed to write metrics response: {err}");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observations_land_in_cumulative_buckets() {
+        let histogram = Histogram::new();
+        histogram.observe(Duration::from_millis(50));
+        histogram.observe(Duration::from_millis(100));
+        histogram.observe(Duration::from_millis(700));
+        histogram.observe(Duration::from_secs(30));
+
+        let mut output = String::new();
+        histogram.render("test_seconds", &mut output);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# TYPE test_seconds histogram");
+        assert_eq!(lines[1], "test_seconds_bucket{le=\"0.1\"} 2");
+        assert_eq!(lines[2], "test_seconds_bucket{le=\"0.25\"} 2");
+        assert_eq!(lines[3], "test_seconds_bucket{le=\"0.5\"} 2");
+        assert_eq!(lines[4], "test_seconds_bucket{le=\"1\"} 3");
+        assert_eq!(lines[7], "test_seconds_bucket{le=\"10\"} 3");
+        assert_eq!(lines[8], "test_seconds_bucket{le=\"+Inf\"} 4");
+        assert_eq!(lines[9], "test_seconds_sum 30.85");
+        assert_eq!(lines[10], "test_seconds_count 4");
+    }
+
+    #[test]
+    fn render_ends_with_eof() {
+        let output = METRICS.render();
+        assert!(output.contains("# TYPE scraper_requests counter\n"));
+        assert!(output.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn renders_the_retry_budget_when_limited() {
+        let metrics = Metrics::new();
+        assert!(!metrics.render().contains("scraper_retries_remaining"));
+        metrics.retries_remaining.store(42, Ordering::Relaxed);
+        assert!(metrics.render().contains("# TYPE scraper_retries_remaining gauge\nscraper_retries_remaining 42\n"));
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{anyhow, bail};
+use serde_json::{Map, Number, Value};
+
+/// Encodes a JSON value as MessagePack
+pub fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(number) => encode_number(number, out),
+        Value::String(string) => {
+            let len = string.len();
+            match len {
+                0..=31 => out.push(0xa0 | len as u8),
+                32..=0xff => out.extend([0xd9, len as u8]),
+                0x100..=0xffff => {
+                    out.push(0xda);
+                    out.extend((len as u16).to_be_bytes());
+                }
+                _ => {
+                    out.push(0xdb);
+                    out.extend((len as u32).to_be_bytes());
+                }
+            }
+            out.extend(string.as_bytes());
+        }
+        Value::Array(values) => {
+            encode_length(values.len(), [0x90, 0xdc, 0xdd], out);
+            for value in values {
+                encode(value, out);
+            }
+        }
+        Value::Object(map) => {
+            encode_length(map.len(), [0x80, 0xde, 0xdf], out);
+            for (key, value) in map {
+                encode(&Value::String(key.clone()), out);
+                encode(value, out);
+            }
+        }
+    }
+}
+
+/// Writes the header of an array or map, given its fix, 16-bit and 32-bit markers
+fn encode_length(len: usize, markers: [u8; 3], out: &mut Vec<u8>) {
+    match len {
+        0..=15 => out.push(markers[0] | len as u8),
+        16..=0xffff => {
+            out.push(markers[1]);
+            out.extend((len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(markers[2]);
+            out.extend((len as u32).to_be_bytes());
+        }
+    }
+}
+
+fn encode_number(number: &Number, out: &mut Vec<u8>) {
+    if let Some(n) = number.as_u64() {
+        match n {
+            0..=0x7f => out.push(n as u8),
+            0x80..=0xff => out.extend([0xcc, n as u8]),
+            0x100..=0xffff => {
+                out.push(0xcd);
+                out.extend((n as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                out.push(0xce);
+                out.extend((n as u32).to_be_bytes());
+            }
+            _ => {
+                out.push(0xcf);
+                out.extend(n.to_be_bytes());
+            }
+        }
+    } else if let Some(n) = number.as_i64() {
+        match n {
+            -32..=-1 => out.push(n as i8 as u8),
+            _ => {
+                out.push(0xd3);
+                out.extend(n.to_be_bytes());
+            }
+        }
+    } else {
+        out.push(0xcb);
+        out.extend(number.as_f64().unwrap_or_default().to_be_bytes());
+    }
+}
+
+/// Decodes a MessagePack document holding a single value
+pub fn decode(data: &[u8]) -> Result<Value, anyhow::Error> {
+    let mut reader = Reader { data, pos: 0 };
+    let value = reader.value()?;
+    if reader.pos != data.len() {
+        bail!("Trailing bytes after MessagePack value at {}", reader.pos);
+    }
+    Ok(value)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], anyhow::Error> {
+        let bytes = self.pos.checked_add(len).and_then(|end| self.data.get(self.pos..end)).ok_or_else(|| anyhow!("Unexpected end of MessagePack data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], anyhow::Error> {
+        Ok(self.take(N)?.try_into().unwrap_or([0; N]))
+    }
+
+    fn u8(&mut self) -> Result<u8, anyhow::Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<usize, anyhow::Error> {
+        Ok(u16::from_be_bytes(self.take_array()?) as usize)
+    }
+
+    fn u32(&mut self) -> Result<usize, anyhow::Error> {
+        Ok(u32::from_be_bytes(self.take_array()?) as usize)
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value, anyhow::Error> {
+        Ok(Value::String(std::str::from_utf8(self.take(len)?)?.to_string()))
+    }
+
+    fn array(&mut self, len: usize) -> Result<Value, anyhow::Error> {
+        let mut values = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            values.push(self.value()?);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value, anyhow::Error> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let key = match self.value()? {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            map.insert(key, self.value()?);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn float(value: f64) -> Result<Value, anyhow::Error> {
+        Number::from_f64(value).map(Value::Number).ok_or_else(|| anyhow!("Non-finite float {value}"))
+    }
+
+    fn value(&mut self) -> Result<Value, anyhow::Error> {
+        let marker = self.u8()?;
+        match marker {
+            0x00..=0x7f => Ok(Value::from(marker)),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize),
+            0x90..=0x9f => self.array((marker & 0x0f) as usize),
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize),
+            0xc0 => Ok(Value::Null),
+            0xc2 => Ok(Value::Bool(false)),
+            0xc3 => Ok(Value::Bool(true)),
+            0xca => Self::float(f32::from_be_bytes(self.take_array()?) as f64),
+            0xcb => Self::float(f64::from_be_bytes(self.take_array()?)),
+            0xcc => Ok(Value::from(self.u8()?)),
+            0xcd => Ok(Value::from(u16::from_be_bytes(self.take_array()?))),
+            0xce => Ok(Value::from(u32::from_be_bytes(self.take_array()?))),
+            0xcf => Ok(Value::from(u64::from_be_bytes(self.take_array()?))),
+            0xd0 => Ok(Value::from(i8::from_be_bytes(self.take_array()?))),
+            0xd1 => Ok(Value::from(i16::from_be_bytes(self.take_array()?))),
+            0xd2 => Ok(Value::from(i32::from_be_bytes(self.take_array()?))),
+            0xd3 => Ok(Value::from(i64::from_be_bytes(self.take_array()?))),
+            0xd9 => {
+                let len = self.u8()? as usize;
+                self.string(len)
+            }
+            0xda => {
+                let len = self.u16()?;
+                self.string(len)
+            }
+            0xdb => {
+                let len = self.u32()?;
+                self.string(len)
+            }
+            0xdc => {
+                let len = self.u16()?;
+                self.array(len)
+            }
+            0xdd => {
+                let len = self.u32()?;
+                self.array(len)
+            }
+            0xde => {
+                let len = self.u16()?;
+                self.map(len)
+            }
+            0xdf => {
+                let len = self.u32()?;
+                self.map(len)
+            }
+            0xe0..=0xff => Ok(Value::from(marker as i8)),
+            marker => bail!("Unsupported MessagePack marker {marker:#x} at {}", self.pos - 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip(value: Value) {
+        let mut data = Vec::new();
+        encode(&value, &mut data);
+        assert_eq!(decode(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn round_trips_values_of_every_width() {
+        for n in [0u64, 0x7f, 0x80, 0xff, 0x100, 0xffff, 0x1_0000, 0xffff_ffff, 0x1_0000_0000, u64::MAX] {
+            round_trip(json!(n));
+        }
+        for n in [-1i64, -32, -33, i64::MIN] {
+            round_trip(json!(n));
+        }
+        round_trip(json!(1.5));
+        round_trip(json!(null));
+        round_trip(json!([true, false]));
+        for len in [0, 31, 32, 0xff, 0x100, 0x1_0000] {
+            round_trip(json!("é".repeat(len / 2)));
+            round_trip(Value::Array(vec![json!(1); len]));
+        }
+        let map = (0..20).map(|i| (format!("key{i}"), json!(i))).collect::<Map<_, _>>();
+        round_trip(Value::Object(map));
+    }
+
+    #[test]
+    fn round_trips_a_chunk() {
+        round_trip(json!({
+            "5558130": {"name": "The.Weapon.2023", "infohash": "4738fb09f6ae3e2f45bca0ffec9f32779500ab03", "seeders": 412, "files": [{"name": "a.mkv", "size": 1395864371u64}]},
+            "5558131": null,
+        }));
+    }
+
+    #[test]
+    fn rejects_truncated_and_trailing_data() {
+        let mut data = Vec::new();
+        encode(&json!({"name": "some torrent"}), &mut data);
+        assert!(decode(&data[..data.len() - 1]).is_err());
+        data.push(0xc0);
+        assert!(decode(&data).is_err());
+        assert!(decode(&[0xc1]).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use std::{fs::OpenOptions, io::{Read, Seek, SeekFrom, Write}, path::PathBuf, time::{Duration, SystemTime, UNIX_EPOCH}};
+use log::*;
+
+/// Rate limiter shared by every process pointing at the same directory.
+///
+/// Each mirror host gets a file holding the next free request slot, in milliseconds.
+/// Processes reserve slots under an exclusive file lock, so they respect a combined rate.
+pub struct SharedRateLimiter {
+    dir: PathBuf,
+    interval_ms: u64,
+}
+
+impl SharedRateLimiter {
+    pub fn new(dir: PathBuf, max_rps: f64) -> Result<Self, anyhow::Error> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(SharedRateLimiter {
+            dir,
+            interval_ms: (1000.0 / max_rps).ceil() as u64,
+        })
+    }
+
+    /// Reserves the next slot for a host and sleeps until it comes
+    pub fn wait(&self, host: &str) -> Result<(), anyhow::Error> {
+        let mut file = OpenOptions::new().create(true).truncate(false).read(true).write(true).open(self.dir.join(format!("{host}.ratelimit")))?;
+        file.lock()?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let next_slot = content.trim().parse::<u64>().unwrap_or_default();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let slot = next_slot.max(now);
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", slot + self.interval_ms)?;
+        file.unlock()?;
+
+        if slot > now {
+            trace!("Waiting {}ms for a request slot on {host}", slot - now);
+            std::thread::sleep(Duration::from_millis(slot - now));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spaces_requests_per_host() {
+        let dir = std::env::temp_dir().join(format!("x1337x-scraper-ratelimit-{}", std::process::id()));
+        let limiter = SharedRateLimiter::new(dir.clone(), 10.0).unwrap();
+        // Another process pointing at the same directory shares the slots
+        let other = SharedRateLimiter::new(dir.clone(), 10.0).unwrap();
+
+        let start = std::time::Instant::now();
+        limiter.wait("1337x.to").unwrap();
+        other.wait("1337x.to").unwrap();
+        limiter.wait("1337x.to").unwrap();
+        // Slots are in whole milliseconds, so the last one can come a bit before 200ms
+        assert!(start.elapsed() >= Duration::from_millis(190));
+
+        let start = std::time::Instant::now();
+        limiter.wait("x1337x.ws").unwrap();
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert!(dir.join("x1337x.ws.ratelimit").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+use log::*;
+use crate::http::HttpClient;
+
+/// Reads the `Crawl-delay` of a robots.txt that applies to a user agent.
+/// A group naming the user agent wins over the `*` group.
+pub fn crawl_delay(robots: &str, user_agent: Option<&str>) -> Option<Duration> {
+    let user_agent = user_agent.unwrap_or_default().to_ascii_lowercase();
+    let (mut specific, mut wildcard) = (None, None);
+    let mut agents: Vec<String> = Vec::new();
+    let mut in_rules = false;
+
+    for line in robots.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+        match key.as_str() {
+            "user-agent" => {
+                // User agents following rules start a new group
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_ascii_lowercase());
+            }
+            "crawl-delay" => {
+                in_rules = true;
+                let Some(delay) = value.parse::<f64>().ok().filter(|delay| delay.is_finite() && *delay >= 0.0) else {
+                    continue;
+                };
+                let delay = Duration::from_secs_f64(delay);
+                if agents.iter().any(|agent| agent != "*" && !user_agent.is_empty() && user_agent.contains(agent.as_str())) {
+                    specific = Some(delay);
+                } else if agents.iter().any(|agent| agent == "*") {
+                    wildcard = Some(delay);
+                }
+            }
+            _ => in_rules = true,
+        }
+    }
+
+    specific.or(wildcard)
+}
+
+/// Fetches the robots.txt of a mirror and reads its crawl delay, treating failures as no delay
+pub fn fetch_crawl_delay(client: &dyn HttpClient, base: &str, user_agent: Option<&str>) -> Option<Duration> {
+    let url = format!("{base}/robots.txt");
+    match client.get(&url) {
+        Ok(resp) if resp.status_code == 200 => crawl_delay(&resp.text(), user_agent),
+        Ok(resp) => {
+            debug!("No robots.txt at {url}: status code {}", resp.status_code);
+            None
+        }
+        Err(err) => {
+            warn!("Failed to fetch {url}: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "User-agent: *\nDisallow: /search\nCrawl-delay: 2 # seconds\n\nUser-agent: friendly-scraper\nUser-agent: other\nCrawl-delay: 0.5\n\nUser-agent: greedy\nCrawl-delay: nope\n";
+
+    #[test]
+    fn prefers_the_group_of_the_user_agent() {
+        assert_eq!(crawl_delay(ROBOTS, Some("Friendly-Scraper/1.0")), Some(Duration::from_millis(500)));
+        assert_eq!(crawl_delay(ROBOTS, Some("curl/8.0")), Some(Duration::from_secs(2)));
+        assert_eq!(crawl_delay(ROBOTS, None), Some(Duration::from_secs(2)));
+        assert_eq!(crawl_delay(ROBOTS, Some("greedy")), Some(Duration::from_secs(2)));
+        assert_eq!(crawl_delay("User-agent: *\nDisallow: /\n", None), None);
+    }
+
+    #[test]
+    fn fetches_from_the_mirror() {
+        use crate::{fixture_server::{FixtureServer, Route}, http::{ClientSettings, MinreqClient}};
+
+        let server = FixtureServer::start([("/robots.txt", Route::ok(ROBOTS))]);
+        let client = MinreqClient { settings: ClientSettings::default() };
+        assert_eq!(fetch_crawl_delay(&client, &server.url(), None), Some(Duration::from_secs(2)));
+        assert_eq!(fetch_crawl_delay(&client, &format!("{}/missing", server.url()), None), None);
+    }
+}
@@ -0,0 +1,1012 @@
+use std::{borrow::Cow, collections::{BTreeMap, BTreeSet}, path::Path, sync::{mpsc, Mutex, OnceLock, RwLock}, thread::JoinHandle, time::Duration};
+use anyhow::bail;
+use log::*;
+use serde::{de::{DeserializeOwned, IgnoredAny}, Serialize, Deserialize};
+use crate::{config::{OptionalField, StashEncoding}, msgpack, TorrentInfo};
+
+pub type Chunk = BTreeMap<usize, Option<TorrentInfo>>;
+
+/// When `None` entries were last checked, stored in `stash/missing/` next to their chunk
+pub type MissingChecks = BTreeMap<usize, u64>;
+
+/// Encoding chunks are written in, JSON unless set. Reads detect the encoding of each chunk.
+static ENCODING: OnceLock<StashEncoding> = OnceLock::new();
+
+/// Directory of the stash, relative to the working directory unless tests point it elsewhere
+static DIR: Mutex<Cow<'static, str>> = Mutex::new(Cow::Borrowed("stash"));
+
+/// How many times writing a chunk is attempted before giving up until the next save
+const WRITE_ATTEMPTS: usize = 3;
+
+/// Delay between attempts at writing a chunk, short in tests as they make writes fail on purpose
+const WRITE_RETRY_DELAY: Duration = match cfg!(test) {
+    true => Duration::from_millis(10),
+    false => Duration::from_secs(1),
+};
+
+/// How many unfolded append logs a chunk can have before they are compacted into it
+const MAX_APPEND_LOGS: usize = 16;
+
+/// Size the unfolded append logs of a chunk can reach before they are compacted into it, small in tests so that they compact often
+const COMPACTION_BYTES: u64 = match cfg!(test) {
+    true => 256,
+    false => 1 << 20,
+};
+
+/// Held for writing while a chunk is rewritten and its logs marked as folded into it, so that reads never miss nor apply logs twice
+static FOLDING: RwLock<()> = RwLock::new(());
+
+/// Held while folding append logs into a chunk, from reading them to merging them into the history, one chunk at a time
+static COMPACTING: Mutex<()> = Mutex::new(());
+
+/// Append logs of each chunk, listed from `stash/append` once per run and kept up to date as logs are written
+static APPEND_LOGS: Mutex<Option<BTreeMap<usize, AppendLogs>>> = Mutex::new(None);
+
+/// Append logs of a chunk. Logs folded into the chunk are kept as the history of its results, but no longer applied to it.
+#[derive(Clone, Default)]
+struct AppendLogs {
+    /// Numbers of the logs, in the order they were written
+    numbers: Vec<usize>,
+    /// Number of the last log folded into the chunk, as recorded in `stash/append/{chunk_id}.folded`
+    folded: Option<usize>,
+}
+
+impl AppendLogs {
+    /// Numbers of the logs that still have to be applied over the chunk
+    fn unfolded(&self) -> Vec<usize> {
+        self.numbers.iter().copied().filter(|n| self.folded.is_none_or(|folded| *n > folded)).collect()
+    }
+}
+
+/// A result stored by an append-only stash, as a line of the append logs of its chunk
+#[derive(Serialize, Deserialize)]
+struct AppendEntry {
+    id: usize,
+    checked_ts: u64,
+    torrent: Option<TorrentInfo>,
+}
+
+pub struct Stash {
+    loaded_chunk: usize,
+    chunk: Chunk,
+    missing_checks: MissingChecks,
+    /// Chunks that were unloaded but couldn't be written, kept until a save succeeds
+    unsaved: BTreeMap<usize, (Chunk, MissingChecks)>,
+    omitted_fields: Vec<OptionalField>,
+    /// Whether results are appended to logs instead of rewriting chunks
+    append_only: bool,
+    /// Results inserted in the loaded chunk since it was last appended to its logs
+    pending: Vec<AppendEntry>,
+    /// Results of append-only stashes that couldn't be written yet, by chunk
+    unsaved_appends: BTreeMap<usize, Vec<AppendEntry>>,
+    /// Folds the append logs into their chunks in the background, for append-only stashes
+    compactor: Option<Compactor>,
+}
+
+/// Folds the append logs of chunks into them on a background thread, once they are due for compaction.
+/// Appends go on to new logs meanwhile. Dropping it waits for the queued chunks.
+struct Compactor {
+    sender: Option<mpsc::Sender<usize>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Compactor {
+    fn spawn() -> Compactor {
+        let (sender, receiver) = mpsc::channel::<usize>();
+        let thread = std::thread::spawn(move || {
+            // A chunk is queued after each append while it is due, so most entries find it compacted already
+            for chunk_id in receiver {
+                match Stash::compaction_due(chunk_id).and_then(|due| if due { Stash::fold(chunk_id) } else { Ok(false) }) {
+                    Ok(true) => debug!("Compacted the append logs of chunk {chunk_id}"),
+                    Ok(false) => (),
+                    Err(err) => error!("Failed to compact the append logs of chunk {chunk_id}: {err:#}"),
+                }
+            }
+        });
+        Compactor { sender: Some(sender), thread: Some(thread) }
+    }
+
+    /// Queues a chunk for compaction if its logs are due
+    fn notify(&self, chunk_id: usize) -> Result<(), anyhow::Error> {
+        if let (Some(sender), true) = (&self.sender, Stash::compaction_due(chunk_id)?) {
+            sender.send(chunk_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Compactor {
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("The compaction thread panicked");
+            }
+        }
+    }
+}
+
+impl Stash {
+    /// Sets the encoding of the chunks written from now on, once per run
+    pub fn set_encoding(encoding: StashEncoding) {
+        let _ = ENCODING.set(encoding);
+    }
+
+    pub fn open() -> Result<Self, anyhow::Error> {
+        let (chunk, missing_checks) = Self::read_merged(0)?;
+
+        Ok(Self {
+            loaded_chunk: 0,
+            chunk,
+            missing_checks,
+            unsaved: BTreeMap::new(),
+            omitted_fields: Vec::new(),
+            append_only: false,
+            pending: Vec::new(),
+            unsaved_appends: BTreeMap::new(),
+            compactor: None,
+        })
+    }
+
+    /// Leaves these fields out of the records inserted from now on
+    pub fn with_omitted_fields(mut self, omitted_fields: Vec<OptionalField>) -> Self {
+        self.omitted_fields = omitted_fields;
+        self
+    }
+
+    /// Appends results to numbered logs next to the chunks instead of rewriting them, keeping every past result.
+    /// Reads apply the logs over the chunks in order, so the latest result of an id wins.
+    /// Stashes that aren't append-only still read the logs, and keep them as history when rewriting chunks.
+    /// Logs are compacted into their chunks on a background thread as they grow.
+    pub fn with_append_only(mut self, append_only: bool) -> Self {
+        self.append_only = append_only;
+        self.compactor = append_only.then(Compactor::spawn);
+        self
+    }
+
+    /// Loads a chunk, which might still be waiting to be written, in place of the current one.
+    /// The current chunk is kept if the new one can't be read.
+    fn load_chunk(&mut self, chunck_id: usize) -> Result<(), anyhow::Error> {
+        debug!("Loading chunk {chunck_id}");
+        let (chunk, missing_checks) = match self.unsaved.remove(&chunck_id) {
+            Some(unsaved) => unsaved,
+            None => {
+                let (mut chunk, mut missing_checks) = Self::read_merged(chunck_id)?;
+                if let Some(entries) = self.unsaved_appends.get(&chunck_id) {
+                    apply_appends(&mut chunk, &mut missing_checks, entries.iter());
+                }
+                (chunk, missing_checks)
+            }
+        };
+
+        // Save current chunk and replace its data
+        let previous = std::mem::replace(&mut self.chunk, chunk);
+        let previous_missing_checks = std::mem::replace(&mut self.missing_checks, missing_checks);
+        match self.append_only {
+            true => self.queue_pending(),
+            false => {
+                self.unsaved.insert(self.loaded_chunk, (previous, previous_missing_checks));
+            }
+        }
+        self.loaded_chunk = chunck_id;
+        if let Err(err) = self.save_unsaved() {
+            error!("{err:#}");
+        }
+        Ok(())
+    }
+
+    /// Moves the results inserted in the loaded chunk to those waiting to be appended
+    fn queue_pending(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        if !pending.is_empty() {
+            self.unsaved_appends.entry(self.loaded_chunk).or_default().extend(pending);
+        }
+    }
+
+    /// Returns the id of the chunk holding an id
+    pub fn chunk_id_of(i: usize) -> usize {
+        i.div_euclid(1000)
+    }
+
+    fn load_item_chunk(&mut self, i: usize) -> Result<(), anyhow::Error> {
+        let chunk_id = Self::chunk_id_of(i);
+        if self.loaded_chunk != chunk_id {
+            self.load_chunk(chunk_id)?;
+        }
+        Ok(())
+    }
+
+    /// Stores the result of checking an id at `checked_ts`
+    pub fn insert(&mut self, i: usize, mut info: Option<TorrentInfo>, checked_ts: u64) -> Result<(), anyhow::Error> {
+        self.load_item_chunk(i)?;
+        if let Some(torrent) = &mut info {
+            torrent.omit_fields(&self.omitted_fields);
+        }
+        match info {
+            Some(_) => self.missing_checks.remove(&i),
+            None => self.missing_checks.insert(i, checked_ts),
+        };
+        if self.append_only {
+            self.pending.push(AppendEntry { id: i, checked_ts, torrent: info.clone() });
+        }
+        self.chunk.insert(i, info);
+        Ok(())
+    }
+
+    pub fn contains_key(&mut self, i: &usize) -> Result<bool, anyhow::Error> {
+        self.load_item_chunk(*i)?;
+        Ok(self.chunk.contains_key(i))
+    }
+
+    /// Tells whether an id is stored with a torrent, as opposed to unknown or known to be missing
+    pub fn holds_torrent(&mut self, i: usize) -> Result<bool, anyhow::Error> {
+        self.load_item_chunk(i)?;
+        Ok(self.chunk.get(&i).is_some_and(Option::is_some))
+    }
+
+    /// Writes the loaded chunk as well as those that previously failed to be written.
+    /// On failure, data stays in memory so that a later save can succeed.
+    pub fn save(&mut self) -> Result<(), anyhow::Error> {
+        if self.append_only {
+            self.queue_pending();
+            return self.save_unsaved();
+        }
+        let loaded_result = Self::write_chunk_retrying(self.loaded_chunk, &self.chunk, &self.missing_checks);
+        let unsaved_result = self.save_unsaved();
+        loaded_result.and(unsaved_result)
+    }
+
+    /// Tries to write every chunk waiting to be saved, keeping those that fail for the next save
+    fn save_unsaved(&mut self) -> Result<(), anyhow::Error> {
+        let mut errors = Vec::new();
+        self.unsaved.retain(|chunk_id, (chunk, missing_checks)| match Self::write_chunk_retrying(*chunk_id, chunk, missing_checks) {
+            Ok(()) => false,
+            Err(err) => {
+                errors.push(err);
+                true
+            }
+        });
+        let mut appended = Vec::new();
+        self.unsaved_appends.retain(|chunk_id, entries| match retrying(*chunk_id, || Self::append_to_log(*chunk_id, entries)) {
+            Ok(()) => {
+                appended.push(*chunk_id);
+                false
+            }
+            Err(err) => {
+                errors.push(err);
+                true
+            }
+        });
+        if let Some(compactor) = &self.compactor {
+            for chunk_id in appended {
+                if let Err(err) = compactor.notify(chunk_id) {
+                    warn!("Failed to queue chunk {chunk_id} for compaction: {err:#}");
+                }
+            }
+        }
+
+        let mut errors = errors.into_iter();
+        let Some(first) = errors.next() else {
+            return Ok(());
+        };
+        for err in errors {
+            error!("{err:#}");
+        }
+        let pending = self.unsaved.len() + self.unsaved_appends.len();
+        Err(first.context(format!("{pending} chunks are waiting to be saved")))
+    }
+
+    fn write_chunk_retrying(chunk_id: usize, chunk: &Chunk, missing_checks: &MissingChecks) -> Result<(), anyhow::Error> {
+        retrying(chunk_id, || Self::write_chunk(chunk_id, chunk, missing_checks))
+    }
+
+    /// Runs `f` over the append logs of every chunk, listing them on first use
+    fn with_append_logs<T>(f: impl FnOnce(&mut BTreeMap<usize, AppendLogs>) -> T) -> Result<T, anyhow::Error> {
+        let mut append_logs = APPEND_LOGS.lock().unwrap();
+        let append_logs = match &mut *append_logs {
+            Some(append_logs) => append_logs,
+            None => append_logs.insert(Self::list_append_logs()?),
+        };
+        Ok(f(append_logs))
+    }
+
+    /// Reads the append logs of each chunk from `stash/append`, sorted in the order they were written
+    fn list_append_logs() -> Result<BTreeMap<usize, AppendLogs>, anyhow::Error> {
+        let entries = match std::fs::read_dir(format!("{}/append", dir())) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut logs = BTreeMap::<usize, AppendLogs>::new();
+        for entry in entries {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if let Some((chunk_id, n)) = parse_append_log_name(&file_name) {
+                logs.entry(chunk_id).or_default().numbers.push(n);
+            } else if let Some(chunk_id) = file_name.strip_suffix(".folded").and_then(|chunk_id| chunk_id.parse().ok()) {
+                logs.entry(chunk_id).or_default().folded = Some(std::fs::read_to_string(entry.path())?.trim().parse()?);
+            }
+        }
+        logs.values_mut().for_each(|logs| logs.numbers.sort_unstable());
+        Ok(logs)
+    }
+
+    /// Lists the append logs of a chunk
+    fn append_logs(chunk_id: usize) -> Result<AppendLogs, anyhow::Error> {
+        Self::with_append_logs(|logs| logs.get(&chunk_id).cloned().unwrap_or_default())
+    }
+
+    /// Lists the ids of the chunks that have append logs
+    fn chunks_with_append_logs() -> Result<Vec<usize>, anyhow::Error> {
+        Self::with_append_logs(|logs| logs.iter().filter(|(_, logs)| !logs.numbers.is_empty()).map(|(chunk_id, _)| *chunk_id).collect())
+    }
+
+    /// Writes results to a new append log of their chunk, numbered after the existing ones
+    fn append_to_log(chunk_id: usize, entries: &[AppendEntry]) -> Result<(), anyhow::Error> {
+        let n = Self::append_logs(chunk_id)?.numbers.last().map_or(0, |n| n + 1);
+        let mut data = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut data, entry)?;
+            data.push(b'\n');
+        }
+        std::fs::create_dir_all(format!("{}/append", dir()))?;
+        write_atomically(&format!("{}/append/{chunk_id}.{n}.ndjson", dir()), &data)?;
+        debug!("Appended {} results to log {n} of chunk {chunk_id}", entries.len());
+        Self::with_append_logs(|append_logs| append_logs.entry(chunk_id).or_default().numbers.push(n))
+    }
+
+    /// Tells whether the unfolded append logs of a chunk are numerous or large enough to be compacted into it
+    fn compaction_due(chunk_id: usize) -> Result<bool, anyhow::Error> {
+        let unfolded = Self::append_logs(chunk_id)?.unfolded();
+        if unfolded.len() >= MAX_APPEND_LOGS {
+            return Ok(true);
+        }
+        let mut size = 0;
+        for n in unfolded {
+            match std::fs::metadata(format!("{}/append/{chunk_id}.{n}.ndjson", dir())) {
+                Ok(metadata) => size += metadata.len(),
+                // Folded and merged into the history meanwhile
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(size >= COMPACTION_BYTES)
+    }
+
+    /// Folds the append logs of a chunk written so far into it, leaving those written meanwhile to the next compaction.
+    /// Returns whether there was anything to fold.
+    fn fold(chunk_id: usize) -> Result<bool, anyhow::Error> {
+        let _compacting = COMPACTING.lock().unwrap();
+        let (chunk, missing_checks, Some(last)) = Self::read_unfolded(chunk_id)? else {
+            return Ok(false);
+        };
+        Self::write_chunk_folding(chunk_id, &chunk, &missing_checks, Some(last))?;
+        Ok(true)
+    }
+
+    /// Records that the append logs of a chunk up to `last` are folded into it, once it was written with them applied.
+    /// Must be called while holding `FOLDING` for writing.
+    fn mark_folded(chunk_id: usize, last: usize) -> Result<(), anyhow::Error> {
+        write_atomically(&format!("{}/append/{chunk_id}.folded", dir()), last.to_string().as_bytes())?;
+        Self::with_append_logs(|logs| logs.entry(chunk_id).or_default().folded = Some(last))
+    }
+
+    /// Concatenates the append logs of a chunk up to `last` into it, so that the logs kept as history don't pile up.
+    /// Reads no longer look at these logs once they are marked as folded.
+    fn merge_history(chunk_id: usize, last: usize) -> Result<(), anyhow::Error> {
+        let numbers: Vec<usize> = Self::append_logs(chunk_id)?.numbers.into_iter().filter(|n| *n <= last).collect();
+        let Some((_, older)) = numbers.split_last().filter(|(_, older)| !older.is_empty()) else {
+            return Ok(());
+        };
+        let mut data = Vec::new();
+        for n in &numbers {
+            data.extend(std::fs::read(format!("{}/append/{chunk_id}.{n}.ndjson", dir()))?);
+        }
+        write_atomically(&format!("{}/append/{chunk_id}.{last}.ndjson", dir()), &data)?;
+
+        // The last log now holds everything the older ones did, so they are only removed once it is written
+        for n in older {
+            std::fs::remove_file(format!("{}/append/{chunk_id}.{n}.ndjson", dir()))?;
+        }
+        Self::with_append_logs(|logs| logs.entry(chunk_id).or_default().numbers.retain(|n| !older.contains(n)))
+    }
+
+    /// Reads the entries of the append logs of a chunk that aren't folded into it yet
+    #[cfg(test)]
+    fn read_appends(chunk_id: usize) -> Result<Vec<AppendEntry>, anyhow::Error> {
+        Self::read_logs(chunk_id, &Self::append_logs(chunk_id)?.unfolded())
+    }
+
+    fn read_logs(chunk_id: usize, numbers: &[usize]) -> Result<Vec<AppendEntry>, anyhow::Error> {
+        let mut entries = Vec::new();
+        for &n in numbers {
+            let data = std::fs::read_to_string(format!("{}/append/{chunk_id}.{n}.ndjson", dir()))?;
+            for line in data.lines().filter(|line| !line.trim().is_empty()) {
+                entries.push(serde_json::from_str(line)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Reads a chunk along with the check times of its `None` entries, with its append logs applied.
+    /// Chunks that were never written are empty.
+    pub fn read_merged(chunk_id: usize) -> Result<(Chunk, MissingChecks), anyhow::Error> {
+        let (chunk, missing_checks, _) = Self::read_unfolded(chunk_id)?;
+        Ok((chunk, missing_checks))
+    }
+
+    /// Reads a chunk with its unfolded append logs applied, along with the number of the last of them
+    fn read_unfolded(chunk_id: usize) -> Result<(Chunk, MissingChecks, Option<usize>), anyhow::Error> {
+        let _reading = FOLDING.read().unwrap();
+        let unfolded = Self::append_logs(chunk_id)?.unfolded();
+        let mut chunk = match Self::read_chunk_data(chunk_id)? {
+            Some((_, data)) => decode_chunk(&data)?,
+            None => Chunk::new(),
+        };
+        let mut missing_checks = Self::read_missing_checks(chunk_id)?;
+        apply_appends(&mut chunk, &mut missing_checks, Self::read_logs(chunk_id, &unfolded)?.iter());
+        Ok((chunk, missing_checks, unfolded.last().copied()))
+    }
+
+    /// Reads the bytes of a chunk along with the encoding its extension tells, if it was ever written
+    fn read_chunk_data(chunk_id: usize) -> Result<Option<(StashEncoding, Vec<u8>)>, anyhow::Error> {
+        for encoding in [StashEncoding::Json, StashEncoding::Msgpack] {
+            match std::fs::read(format!("{}/{chunk_id}.{}", dir(), extension(encoding))) {
+                Ok(data) => return Ok(Some((encoding, data))),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Lists the ids of the chunks present on disk, including those only held by append logs, in ascending order
+    pub fn chunk_ids() -> Result<Vec<usize>, anyhow::Error> {
+        let mut chunk_ids = BTreeSet::new();
+        for entry in std::fs::read_dir(dir())? {
+            let file_name = entry?.file_name();
+            let file_name = file_name.to_string_lossy();
+            if let Some((chunk_id, _)) = parse_chunk_name(&file_name) {
+                chunk_ids.insert(chunk_id);
+            }
+        }
+        chunk_ids.extend(Self::chunks_with_append_logs()?);
+        Ok(chunk_ids.into_iter().collect())
+    }
+
+    /// Reads a chunk from disk with its append logs applied, independently of the loaded one
+    pub fn read_chunk(chunk_id: usize) -> Result<Chunk, anyhow::Error> {
+        Ok(Self::read_merged(chunk_id)?.0)
+    }
+
+    /// Counts the entries of each chunk, one chunk at a time and without deserializing torrents
+    fn count_entries() -> Result<(usize, usize), anyhow::Error> {
+        let (mut present, mut missing) = (0, 0);
+        for chunk_id in Self::chunk_ids()? {
+            let (chunk_present, chunk_len) = match Self::append_logs(chunk_id)?.unfolded().is_empty() {
+                true => {
+                    let Some((_, chunk_data)) = Self::read_chunk_data(chunk_id)? else {
+                        continue;
+                    };
+                    let chunk: BTreeMap<usize, Option<IgnoredAny>> = decode_chunk(&chunk_data)?;
+                    (chunk.values().filter(|info| info.is_some()).count(), chunk.len())
+                }
+                false => {
+                    let chunk = Self::read_chunk(chunk_id)?;
+                    (chunk.values().filter(|info| info.is_some()).count(), chunk.len())
+                }
+            };
+            present += chunk_present;
+            missing += chunk_len - chunk_present;
+        }
+        Ok((present, missing))
+    }
+
+    /// Counts stashed torrents
+    pub fn count_present() -> Result<usize, anyhow::Error> {
+        Ok(Self::count_entries()?.0)
+    }
+
+    /// Counts ids known not to hold a torrent
+    pub fn count_missing() -> Result<usize, anyhow::Error> {
+        Ok(Self::count_entries()?.1)
+    }
+
+    /// Reads a single torrent from disk
+    pub fn read_torrent(i: usize) -> Result<Option<TorrentInfo>, anyhow::Error> {
+        let mut chunk = Self::read_chunk(Self::chunk_id_of(i))?;
+        Ok(chunk.remove(&i).flatten())
+    }
+
+    /// Reads the check times of a chunk's `None` entries, which are absent for chunks written before they were tracked
+    fn read_missing_checks(chunk_id: usize) -> Result<MissingChecks, anyhow::Error> {
+        match std::fs::read_to_string(format!("{}/missing/{chunk_id}.json", dir())) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(MissingChecks::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Writes a chunk through temporary files, so that a failed write never corrupts the existing chunk.
+    /// The chunk must have its append logs applied, as read by `read_merged`, since they are then recorded as folded into it.
+    pub fn write_chunk(chunk_id: usize, chunk: &Chunk, missing_checks: &MissingChecks) -> Result<(), anyhow::Error> {
+        let _compacting = COMPACTING.lock().unwrap();
+        let last = Self::append_logs(chunk_id)?.numbers.last().copied();
+        Self::write_chunk_folding(chunk_id, chunk, missing_checks, last)
+    }
+
+    /// Writes a chunk holding its append logs up to `last`, recording them as folded into it.
+    /// Must be called while holding `COMPACTING`.
+    fn write_chunk_folding(chunk_id: usize, chunk: &Chunk, missing_checks: &MissingChecks, last: Option<usize>) -> Result<(), anyhow::Error> {
+        let writing = FOLDING.write().unwrap();
+        let folded = Self::append_logs(chunk_id)?.folded;
+        let encoding = ENCODING.get().copied().unwrap_or_default();
+        write_atomically(&format!("{}/{chunk_id}.{}", dir(), extension(encoding)), &encode_chunk(chunk, encoding)?)?;
+        // A chunk written in another encoding before would shadow or be shadowed by this one
+        for other in [StashEncoding::Json, StashEncoding::Msgpack].into_iter().filter(|other| *other != encoding) {
+            match std::fs::remove_file(format!("{}/{chunk_id}.{}", dir(), extension(other))) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => (),
+            }
+        }
+        std::fs::create_dir_all(format!("{}/missing", dir()))?;
+        write_atomically(&format!("{}/missing/{chunk_id}.json", dir()), serde_json::to_string(missing_checks)?.as_bytes())?;
+        let Some(last) = last.filter(|last| folded != Some(*last)) else {
+            return Ok(());
+        };
+        Self::mark_folded(chunk_id, last)?;
+        drop(writing);
+        Self::merge_history(chunk_id, last)?;
+        debug!("Folded the append logs of chunk {chunk_id} into it");
+        Ok(())
+    }
+
+    /// Keeps the comments JSON of a torrent as served, so that comments can be parsed again later.
+    /// Pages after the first one get their own file.
+    pub fn write_raw_comments(i: usize, page: usize, data: &str) -> Result<(), anyhow::Error> {
+        std::fs::create_dir_all(format!("{}/comments", dir()))?;
+        let path = match page {
+            1 => format!("{}/comments/{i}.json", dir()),
+            page => format!("{}/comments/{i}.{page}.json", dir()),
+        };
+        write_atomically(&path, data.as_bytes())?;
+        Ok(())
+    }
+
+    /// Reads the id the last scan stopped at, if a scan ever saved one
+    pub fn read_cursor() -> Result<Option<usize>, anyhow::Error> {
+        match std::fs::read_to_string(format!("{}/cursor", dir())) {
+            Ok(data) => Ok(Some(data.trim().parse()?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Records the next id to scan. Only call this once the ids before it are saved.
+    pub fn write_cursor(next_id: usize) -> Result<(), anyhow::Error> {
+        write_atomically(&format!("{}/cursor", dir()), next_id.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    /// Rewrites chunks whose bytes differ from how they would be written now, such as chunks written by older versions,
+    /// and folds append logs into their chunks. Running it again changes nothing. Returns how many chunks were rewritten.
+    pub fn compact() -> Result<usize, anyhow::Error> {
+        let encoding = ENCODING.get().copied().unwrap_or_default();
+        let mut rewritten = 0;
+        for chunk_id in Self::chunk_ids()? {
+            let (named_encoding, data) = Self::read_chunk_data(chunk_id)?.unwrap_or((encoding, Vec::new()));
+            let (chunk, missing_checks) = Self::read_merged(chunk_id)?;
+            if encode_chunk(&chunk, encoding)? != data || named_encoding != encoding || !Self::append_logs(chunk_id)?.unfolded().is_empty() {
+                Self::write_chunk(chunk_id, &chunk, &missing_checks)?;
+                rewritten += 1;
+                debug!("Compacted chunk {chunk_id}");
+            }
+        }
+        Ok(rewritten)
+    }
+
+    /// Removes `None` entries last checked before `threshold_ts` so that they get probed again.
+    /// Entries without a known check time are recorded as checked at `now`, so that they are pruned once they age.
+    pub fn prune_missing(now: u64, threshold_ts: u64) -> Result<usize, anyhow::Error> {
+        let mut pruned = 0;
+        for chunk_id in Self::chunk_ids()? {
+            let (mut chunk, mut missing_checks) = Self::read_merged(chunk_id)?;
+            let (chunk_pruned, recorded) = prune_chunk(&mut chunk, &mut missing_checks, now, threshold_ts);
+            if chunk_pruned > 0 || recorded > 0 {
+                pruned += chunk_pruned;
+                Self::write_chunk(chunk_id, &chunk, &missing_checks)?;
+                debug!("Pruned {chunk_pruned} entries from chunk {chunk_id}, recorded the check time of {recorded}");
+            }
+        }
+        Ok(pruned)
+    }
+}
+
+/// Returns the directory of the stash
+pub fn dir() -> String {
+    DIR.lock().unwrap().to_string()
+}
+
+/// Points the stash to an empty temporary directory until dropped, one test at a time since the stash is shared
+#[cfg(test)]
+pub struct TempStash {
+    path: std::path::PathBuf,
+    _guard: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl TempStash {
+    pub fn new(name: &str) -> TempStash {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let guard = LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let path = std::env::temp_dir().join(format!("x1337x-scraper-stash-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        *DIR.lock().unwrap() = Cow::Owned(path.to_string_lossy().into_owned());
+        *APPEND_LOGS.lock().unwrap() = None;
+        TempStash { path, _guard: guard }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempStash {
+    fn drop(&mut self) {
+        *DIR.lock().unwrap() = Cow::Borrowed("stash");
+        *APPEND_LOGS.lock().unwrap() = None;
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Prunes the `None` entries of a chunk, returning how many were removed and how many got a check time of `now`
+fn prune_chunk(chunk: &mut Chunk, missing_checks: &mut MissingChecks, now: u64, threshold_ts: u64) -> (usize, usize) {
+    let mut recorded = 0;
+    for (i, _) in chunk.iter().filter(|(_, info)| info.is_none()) {
+        missing_checks.entry(*i).or_insert_with(|| {
+            recorded += 1;
+            now
+        });
+    }
+    let len_before = chunk.len();
+    chunk.retain(|i, info| info.is_some() || missing_checks.get(i).is_some_and(|checked_ts| *checked_ts >= threshold_ts));
+    missing_checks.retain(|i, _| chunk.contains_key(i));
+    (len_before - chunk.len(), recorded)
+}
+
+/// Runs a write of a chunk until it succeeds, up to `WRITE_ATTEMPTS` times
+fn retrying(chunk_id: usize, mut write: impl FnMut() -> Result<(), anyhow::Error>) -> Result<(), anyhow::Error> {
+    let mut attempt = 1;
+    loop {
+        match write() {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < WRITE_ATTEMPTS => {
+                error!("Failed to save chunk {chunk_id} (attempt {attempt}/{WRITE_ATTEMPTS}): {err}");
+                std::thread::sleep(WRITE_RETRY_DELAY);
+                attempt += 1;
+            }
+            Err(err) => return Err(err.context(format!("Failed to save chunk {chunk_id}"))),
+        }
+    }
+}
+
+/// Reads the chunk id and number of an append log from its file name, such as `12.3.ndjson`
+fn parse_append_log_name(file_name: &str) -> Option<(usize, usize)> {
+    let (chunk_id, n) = file_name.strip_suffix(".ndjson")?.split_once('.')?;
+    Some((chunk_id.parse().ok()?, n.parse().ok()?))
+}
+
+/// Applies append log entries over a chunk, in order
+fn apply_appends<'a>(chunk: &mut Chunk, missing_checks: &mut MissingChecks, entries: impl Iterator<Item = &'a AppendEntry>) {
+    for entry in entries {
+        match entry.torrent {
+            Some(_) => missing_checks.remove(&entry.id),
+            None => missing_checks.insert(entry.id, entry.checked_ts),
+        };
+        chunk.insert(entry.id, entry.torrent.clone());
+    }
+}
+
+/// Extension of the chunks written in an encoding
+fn extension(encoding: StashEncoding) -> &'static str {
+    match encoding {
+        StashEncoding::Json => "json",
+        StashEncoding::Msgpack => "msgpack",
+    }
+}
+
+/// Reads the id and the encoding of a chunk from its file name, such as `12.json` or `12.msgpack`
+fn parse_chunk_name(file_name: &str) -> Option<(usize, StashEncoding)> {
+    let (chunk_id, extension) = file_name.split_once('.')?;
+    let encoding = match extension {
+        "json" => StashEncoding::Json,
+        "msgpack" => StashEncoding::Msgpack,
+        _ => return None,
+    };
+    Some((chunk_id.parse().ok()?, encoding))
+}
+
+/// Tells JSON chunks, which start with `{`, from MessagePack ones, which start with a map marker.
+/// Chunks are named after their encoding, but MessagePack chunks written before that were named `.json`.
+pub fn encoding_of(data: &[u8]) -> StashEncoding {
+    match data.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') | None => StashEncoding::Json,
+        Some(_) => StashEncoding::Msgpack,
+    }
+}
+
+fn decode_chunk<T: DeserializeOwned>(data: &[u8]) -> Result<T, anyhow::Error> {
+    match encoding_of(data) {
+        StashEncoding::Json => Ok(serde_json::from_slice(data)?),
+        StashEncoding::Msgpack => Ok(serde_json::from_value(msgpack::decode(data)?)?),
+    }
+}
+
+fn encode_chunk(chunk: &Chunk, encoding: StashEncoding) -> Result<Vec<u8>, anyhow::Error> {
+    match encoding {
+        StashEncoding::Json => Ok(serde_json::to_vec_pretty(chunk)?),
+        StashEncoding::Msgpack => {
+            let mut data = Vec::new();
+            msgpack::encode(&serde_json::to_value(chunk)?, &mut data);
+            Ok(data)
+        }
+    }
+}
+
+/// Re-encodes the chunks of the stash in `input` into `output`, along with their sidecars, returning how many records were converted.
+/// Chunks that aren't in the `from` encoding, when given, are an error.
+pub fn convert(input: &Path, output: &Path, from: Option<StashEncoding>, to: StashEncoding) -> Result<usize, anyhow::Error> {
+    std::fs::create_dir_all(output.join("missing"))?;
+    let mut records = 0;
+    for entry in std::fs::read_dir(input)? {
+        let path = entry?.path();
+        let Some((chunk_id, _)) = path.file_name().and_then(|name| parse_chunk_name(name.to_str()?)) else {
+            continue;
+        };
+
+        let data = std::fs::read(&path)?;
+        if from.is_some_and(|from| from != encoding_of(&data)) {
+            bail!("Chunk {chunk_id} is encoded as {:?}, not {from:?}", encoding_of(&data));
+        }
+        let chunk: Chunk = decode_chunk(&data)?;
+        let out_path = output.join(format!("{chunk_id}.{}", extension(to)));
+        write_atomically(&out_path.to_string_lossy(), &encode_chunk(&chunk, to)?)?;
+
+        let converted: Chunk = decode_chunk(&std::fs::read(&out_path)?)?;
+        if converted.len() != chunk.len() {
+            bail!("Chunk {chunk_id} has {} records after conversion instead of {}", converted.len(), chunk.len());
+        }
+        records += chunk.len();
+
+        let missing_path = input.join(format!("missing/{chunk_id}.json"));
+        if missing_path.exists() {
+            std::fs::copy(missing_path, output.join(format!("missing/{chunk_id}.json")))?;
+        }
+        debug!("Converted chunk {chunk_id}");
+    }
+
+    // Append logs are JSON whatever the encoding of the chunks, so they are copied as they are
+    if let Ok(entries) = std::fs::read_dir(input.join("append")) {
+        std::fs::create_dir_all(output.join("append"))?;
+        for entry in entries {
+            let entry = entry?;
+            std::fs::copy(entry.path(), output.join("append").join(entry.file_name()))?;
+        }
+    }
+    Ok(records)
+}
+
+fn write_atomically(path: &str, data: &[u8]) -> Result<(), std::io::Error> {
+    let tmp_path = format!("{path}.tmp");
+    if let Err(err) = std::fs::write(&tmp_path, data) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+    std::fs::rename(tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: usize, checked_ts: u64) -> AppendEntry {
+        AppendEntry { id, checked_ts, torrent: None }
+    }
+
+    #[test]
+    fn parses_append_log_names() {
+        assert_eq!(parse_append_log_name("12.3.ndjson"), Some((12, 3)));
+        assert_eq!(parse_append_log_name("0.15.ndjson"), Some((0, 15)));
+        assert_eq!(parse_append_log_name("12.3.ndjson.tmp"), None);
+        assert_eq!(parse_append_log_name("12.ndjson"), None);
+        assert_eq!(parse_append_log_name("12.x.ndjson"), None);
+    }
+
+    #[test]
+    fn latest_append_wins() {
+        let mut chunk = Chunk::new();
+        let mut missing_checks = MissingChecks::new();
+        let entries = [entry(1, 10), entry(2, 10), entry(1, 20)];
+        apply_appends(&mut chunk, &mut missing_checks, entries.iter());
+        assert_eq!(chunk.len(), 2);
+        assert_eq!(missing_checks, MissingChecks::from([(1, 20), (2, 10)]));
+    }
+
+    #[test]
+    fn both_scrapes_of_an_id_stay_in_the_append_log() {
+        let _stash_dir = TempStash::new("history");
+        for checked_ts in [10, 20] {
+            let mut stash = Stash::open().unwrap().with_append_only(true);
+            stash.insert(5, None, checked_ts).unwrap();
+            stash.save().unwrap();
+        }
+        let history = |chunk_id| Stash::read_logs(chunk_id, &Stash::append_logs(chunk_id).unwrap().numbers).unwrap();
+        let checks = |entries: Vec<AppendEntry>| entries.iter().map(|entry| (entry.id, entry.checked_ts)).collect::<Vec<_>>();
+        assert_eq!(checks(history(0)), [(5, 10), (5, 20)]);
+        assert_eq!(Stash::read_merged(0).unwrap().1, MissingChecks::from([(5, 20)]));
+
+        // Rewriting the chunk folds the logs into it without losing them
+        assert_eq!(Stash::compact().unwrap(), 1);
+        assert_eq!(Stash::compact().unwrap(), 0);
+        assert_eq!(checks(history(0)), [(5, 10), (5, 20)]);
+        assert!(Stash::read_appends(0).unwrap().is_empty());
+        assert_eq!(Stash::read_merged(0).unwrap().1, MissingChecks::from([(5, 20)]));
+
+        // Logs don't make later stashes append-only
+        let mut stash = Stash::open().unwrap();
+        assert!(!stash.append_only);
+        stash.insert(6, None, 30).unwrap();
+        stash.save().unwrap();
+        assert_eq!(checks(history(0)), [(5, 10), (5, 20)]);
+        assert_eq!(Stash::read_chunk(0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn appends_during_compaction_are_kept() {
+        let _stash_dir = TempStash::new("compaction");
+        let mut stash = Stash::open().unwrap().with_append_only(true);
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            // Compacts and reads alongside the background thread, checking that no read ever loses an entry
+            scope.spawn(|| {
+                let mut seen = 0;
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    Stash::fold(0).unwrap();
+                    let len = Stash::read_chunk(0).unwrap().len();
+                    assert!(len >= seen, "read {len} entries after {seen}");
+                    seen = len;
+                }
+            });
+            for i in 0..300 {
+                stash.insert(i, None, i as u64).unwrap();
+                stash.save().unwrap();
+            }
+            done.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+        drop(stash);
+
+        assert_eq!(Stash::read_chunk(0).unwrap().keys().copied().collect::<Vec<_>>(), (0..300).collect::<Vec<_>>());
+        let history = Stash::read_logs(0, &Stash::append_logs(0).unwrap().numbers).unwrap();
+        assert_eq!(history.iter().map(|entry| entry.id).collect::<Vec<_>>(), (0..300).collect::<Vec<_>>());
+        assert!(Stash::append_logs(0).unwrap().unfolded().len() < MAX_APPEND_LOGS);
+    }
+
+    fn torrent() -> TorrentInfo {
+        crate::parse_torrent_html(include_str!("../test.html"), 1_700_000_000, crate::COMMENT_COUNT_SELECTOR).unwrap().unwrap()
+    }
+
+    #[test]
+    fn counts_present_and_missing_ids() {
+        let _stash_dir = TempStash::new("count");
+        assert_eq!((Stash::count_present().unwrap(), Stash::count_missing().unwrap()), (0, 0));
+
+        let mut stash = Stash::open().unwrap();
+        for i in [1, 2, 1001] {
+            stash.insert(i, Some(torrent()), 10).unwrap();
+        }
+        for i in [3, 2002] {
+            stash.insert(i, None, 10).unwrap();
+        }
+        stash.save().unwrap();
+        assert_eq!((Stash::count_present().unwrap(), Stash::count_missing().unwrap()), (3, 2));
+
+        // Entries still in append logs count too, with a later check replacing an earlier one
+        let mut stash = Stash::open().unwrap().with_append_only(true);
+        stash.insert(3, Some(torrent()), 20).unwrap();
+        stash.insert(4, None, 20).unwrap();
+        stash.save().unwrap();
+        assert_eq!((Stash::count_present().unwrap(), Stash::count_missing().unwrap()), (4, 2));
+    }
+
+    #[test]
+    fn compacting_twice_changes_nothing() {
+        let stash_dir = TempStash::new("compact-twice");
+        let mut stash = Stash::open().unwrap();
+        stash.insert(1001, Some(torrent()), 10).unwrap();
+        stash.save().unwrap();
+        // As written by hand or by an older version
+        std::fs::write(stash_dir.path().join("0.json"), "{\n  \"2\": null,\n  \"1\": null\n}\n").unwrap();
+
+        assert_eq!(Stash::compact().unwrap(), 1);
+        let compacted = std::fs::read(stash_dir.path().join("0.json")).unwrap();
+        assert_eq!(Stash::compact().unwrap(), 0);
+        assert_eq!(std::fs::read(stash_dir.path().join("0.json")).unwrap(), compacted);
+        assert_eq!(Stash::read_chunk(0).unwrap().keys().copied().collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[test]
+    fn prunes_stale_missing_entries_only() {
+        let mut chunk = Chunk::from([(1, None), (2, None), (3, None)]);
+        let mut missing_checks = MissingChecks::from([(1, 100), (2, 300)]);
+        assert_eq!(prune_chunk(&mut chunk, &mut missing_checks, 1000, 200), (1, 1));
+        assert_eq!(chunk.keys().copied().collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(missing_checks, MissingChecks::from([(2, 300), (3, 1000)]));
+
+        // Entries checked for the first time above age like the others
+        assert_eq!(prune_chunk(&mut chunk, &mut missing_checks, 2000, 1500), (2, 0));
+        assert!(chunk.is_empty() && missing_checks.is_empty());
+    }
+
+    #[test]
+    fn parses_chunk_names() {
+        assert_eq!(parse_chunk_name("12.json"), Some((12, StashEncoding::Json)));
+        assert_eq!(parse_chunk_name("12.msgpack"), Some((12, StashEncoding::Msgpack)));
+        assert_eq!(parse_chunk_name("12.json.tmp"), None);
+        assert_eq!(parse_chunk_name("cursor"), None);
+        assert_eq!(parse_chunk_name("a.json"), None);
+    }
+
+    #[test]
+    fn converts_chunks_to_their_extension() {
+        let dir = std::env::temp_dir().join(format!("x1337x-scraper-convert-{}", std::process::id()));
+        let (json, msgpack, back) = (dir.join("json"), dir.join("msgpack"), dir.join("back"));
+        std::fs::create_dir_all(json.join("missing")).unwrap();
+        let chunk = Chunk::from([(5001, None), (5002, None)]);
+        std::fs::write(json.join("5.json"), encode_chunk(&chunk, StashEncoding::Json).unwrap()).unwrap();
+        std::fs::write(json.join("missing/5.json"), r#"{"5001":10,"5002":20}"#).unwrap();
+
+        assert_eq!(convert(&json, &msgpack, Some(StashEncoding::Json), StashEncoding::Msgpack).unwrap(), 2);
+        assert!(!msgpack.join("5.json").exists());
+        let data = std::fs::read(msgpack.join("5.msgpack")).unwrap();
+        assert_eq!(encoding_of(&data), StashEncoding::Msgpack);
+        assert!(msgpack.join("missing/5.json").exists());
+        assert!(convert(&msgpack, &back, Some(StashEncoding::Json), StashEncoding::Json).is_err());
+
+        assert_eq!(convert(&msgpack, &back, None, StashEncoding::Json).unwrap(), 2);
+        assert_eq!(std::fs::read(back.join("5.json")).unwrap(), std::fs::read(json.join("5.json")).unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn detects_chunk_encodings() {
+        assert_eq!(encoding_of(b"  {\"1\": null}"), StashEncoding::Json);
+        assert_eq!(encoding_of(b""), StashEncoding::Json);
+        assert_eq!(encoding_of(&[0x81, 0xa1, b'1', 0xc0]), StashEncoding::Msgpack);
+    }
+
+    #[test]
+    fn keeps_every_unsaved_chunk_when_writes_fail() {
+        let stash_dir = TempStash::new("unwritable");
+        // Permissions don't stop root, so the temporary files chunks are written through are taken by directories instead
+        let blockers = [0, 1, 2].map(|chunk_id| stash_dir.path().join(format!("{chunk_id}.json.tmp")));
+        blockers.iter().for_each(|blocker| std::fs::create_dir(blocker).unwrap());
+
+        let mut stash = Stash::open().unwrap();
+        stash.insert(1, None, 10).unwrap();
+        stash.insert(1001, None, 10).unwrap();
+        stash.insert(2001, None, 10).unwrap();
+        assert!(stash.save().is_err());
+        assert_eq!(stash.unsaved.keys().copied().collect::<Vec<_>>(), [0, 1]);
+        assert!(stash.contains_key(&1).unwrap());
+
+        blockers.iter().for_each(|blocker| std::fs::remove_dir(blocker).unwrap());
+        stash.save().unwrap();
+        assert!(stash.unsaved.is_empty());
+        for i in [1, 1001, 2001] {
+            assert_eq!(Stash::read_chunk(Stash::chunk_id_of(i)).unwrap().keys().copied().collect::<Vec<_>>(), [i]);
+        }
+    }
+}
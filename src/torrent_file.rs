@@ -0,0 +1,136 @@
+use anyhow::{anyhow, bail};
+use crate::infohash::InfoHash;
+
+/// Returns the position right after the bencoded value starting at `pos`
+fn skip_value(data: &[u8], pos: usize) -> Result<usize, anyhow::Error> {
+    match data.get(pos) {
+        Some(b'i') => {
+            let end = data[pos..].iter().position(|b| *b == b'e').ok_or_else(|| anyhow!("Unterminated integer at {pos}"))?;
+            Ok(pos + end + 1)
+        }
+        Some(b'l') | Some(b'd') => {
+            let mut pos = pos + 1;
+            while data.get(pos) != Some(&b'e') {
+                if pos >= data.len() {
+                    bail!("Unterminated list or dictionary");
+                }
+                pos = skip_value(data, pos)?;
+            }
+            Ok(pos + 1)
+        }
+        Some(b'0'..=b'9') => Ok(byte_string_bounds(data, pos)?.1),
+        Some(byte) => bail!("Unexpected byte {byte:#x} at {pos}"),
+        None => bail!("Unexpected end of data"),
+    }
+}
+
+/// Returns the bounds of the content of the byte string starting at `pos`
+fn byte_string_bounds(data: &[u8], pos: usize) -> Result<(usize, usize), anyhow::Error> {
+    let colon = data[pos..].iter().position(|b| *b == b':').ok_or_else(|| anyhow!("Unterminated string length at {pos}"))?;
+    let len: usize = std::str::from_utf8(&data[pos..pos + colon])?.parse()?;
+    let start = pos + colon + 1;
+    let end = start.checked_add(len).filter(|end| *end <= data.len()).ok_or_else(|| anyhow!("String at {pos} overflows the data"))?;
+    Ok((start, end))
+}
+
+/// Finds the raw bencoded `info` dictionary of a .torrent file
+fn info_dict(data: &[u8]) -> Result<&[u8], anyhow::Error> {
+    if data.first() != Some(&b'd') {
+        bail!("Not a bencoded dictionary");
+    }
+
+    let mut pos = 1;
+    while data.get(pos) != Some(&b'e') {
+        let (key_start, key_end) = byte_string_bounds(data, pos)?;
+        let value_end = skip_value(data, key_end)?;
+        if &data[key_start..key_end] == b"info" {
+            return Ok(&data[key_end..value_end]);
+        }
+        pos = value_end;
+    }
+    bail!("No info dictionary")
+}
+
+/// Computes the v1 infohash of a .torrent file, in lowercase hex
+pub fn infohash(data: &[u8]) -> Result<InfoHash, anyhow::Error> {
+    let digest = sha1(info_dict(data)?);
+    digest.iter().map(|b| format!("{b:02x}")).collect::<String>().parse()
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (i, h) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(digest: [u8; 20]) -> String {
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn sha1_matches_known_digests() {
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Spans several blocks
+        assert_eq!(hex(sha1(&[b'a'; 1000])), "291e9a6c66994949b57ba5e650361e98fc36b1ba");
+    }
+
+    #[test]
+    fn infohash_of_fixture() {
+        let data = include_bytes!("../fixtures/sample.torrent");
+        assert_eq!(infohash(data).unwrap().as_str(), "717c2cc5276358201c6674cc391207140d65c344");
+    }
+
+    #[test]
+    fn rejects_files_without_info() {
+        assert!(infohash(b"d8:announce3:urle").is_err());
+        assert!(infohash(b"i42e").is_err());
+        assert!(infohash(b"d4:info").is_err());
+        assert!(infohash(b"d4:infod4:name99:shorte").is_err());
+    }
+}
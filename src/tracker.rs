@@ -0,0 +1,94 @@
+use std::collections::{BTreeMap, HashSet};
+
+/// Protocol of a tracker URL, ordered by preference for magnet links
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TrackerProtocol {
+    Udp,
+    Https,
+    Http,
+    Wss,
+    Ws,
+    Other,
+}
+
+impl TrackerProtocol {
+    pub fn of(url: &str) -> TrackerProtocol {
+        let scheme = url.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase());
+        match scheme.as_deref() {
+            Some("udp") => TrackerProtocol::Udp,
+            Some("https") => TrackerProtocol::Https,
+            Some("http") => TrackerProtocol::Http,
+            Some("wss") => TrackerProtocol::Wss,
+            Some("ws") => TrackerProtocol::Ws,
+            _ => TrackerProtocol::Other,
+        }
+    }
+}
+
+/// Tells whether a URL of the tracker list is a web seed rather than an announce URL.
+/// Web seeds are listed in their own section when the page tells them apart, otherwise they are HTTP URLs that don't announce.
+pub fn is_web_seed(url: &str, section: &str) -> bool {
+    if section.to_ascii_lowercase().contains("seed") {
+        return true;
+    }
+    matches!(TrackerProtocol::of(url), TrackerProtocol::Http | TrackerProtocol::Https) && !url.contains("announce")
+}
+
+/// Returns the trackers in page order, keeping the first of duplicates.
+/// The site doesn't tell tiers apart, so the page order is the only preference there is.
+pub fn unique(trackers: &[String]) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    trackers.iter().map(|tracker| tracker.trim()).filter(|tracker| seen.insert(*tracker)).collect()
+}
+
+/// Groups unique trackers by protocol, keeping their page order within each group
+pub fn group_by_protocol(trackers: &[String]) -> BTreeMap<TrackerProtocol, Vec<&str>> {
+    let mut groups: BTreeMap<TrackerProtocol, Vec<&str>> = BTreeMap::new();
+    for tracker in unique(trackers) {
+        groups.entry(TrackerProtocol::of(tracker)).or_default().push(tracker);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trackers() -> Vec<String> {
+        [
+            "http://tracker.example/announce",
+            "udp://open.example:1337/announce",
+            "wss://tracker.webtorrent.example",
+            "udp://open.example:1337/announce",
+            "https://secure.example/announce",
+            "udp://other.example:6969/announce",
+        ].map(String::from).to_vec()
+    }
+
+    #[test]
+    fn keeps_page_order_without_duplicates() {
+        assert_eq!(unique(&trackers()), [
+            "http://tracker.example/announce",
+            "udp://open.example:1337/announce",
+            "wss://tracker.webtorrent.example",
+            "https://secure.example/announce",
+            "udp://other.example:6969/announce",
+        ]);
+    }
+
+    #[test]
+    fn groups_mixed_trackers_by_protocol() {
+        let trackers = trackers();
+        let groups = group_by_protocol(&trackers);
+        assert_eq!(groups.keys().copied().collect::<Vec<_>>(), [TrackerProtocol::Udp, TrackerProtocol::Https, TrackerProtocol::Http, TrackerProtocol::Wss]);
+        assert_eq!(groups[&TrackerProtocol::Udp], ["udp://open.example:1337/announce", "udp://other.example:6969/announce"]);
+    }
+
+    #[test]
+    fn tells_web_seeds_apart() {
+        assert!(is_web_seed("http://tracker.example/announce", "Web seeds:"));
+        assert!(is_web_seed("https://mirror.example/files/", "Trackers:"));
+        assert!(!is_web_seed("http://tracker.example/announce", "Trackers:"));
+        assert!(!is_web_seed("udp://open.example:1337", "Trackers:"));
+    }
+}
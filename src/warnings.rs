@@ -0,0 +1,92 @@
+use std::{collections::BTreeMap, sync::Mutex, time::{Duration, Instant}};
+use log::*;
+
+/// Coalesces the warnings met while scraping, which would otherwise flood the logs during full scans
+pub static WARNINGS: WarningAggregator = WarningAggregator::new(Duration::from_secs(60));
+
+pub struct WarningAggregator {
+    window: Duration,
+    /// Warnings by kind, the part of the message before the first ": "
+    kinds: Mutex<BTreeMap<String, Window>>,
+}
+
+struct Window {
+    started: Instant,
+    /// Warnings of this kind that weren't logged since the window started
+    suppressed: usize,
+}
+
+impl WarningAggregator {
+    const fn new(window: Duration) -> Self {
+        WarningAggregator {
+            window,
+            kinds: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Logs the first warning of each kind per window, only counting the others
+    pub fn warn(&self, message: &str) {
+        let kind = message.split_once(": ").map_or(message, |(kind, _)| kind);
+        let now = Instant::now();
+        let mut kinds = self.kinds.lock().unwrap();
+        match kinds.get_mut(kind) {
+            Some(window) if now.duration_since(window.started) < self.window => {
+                window.suppressed += 1;
+                return;
+            }
+            Some(window) => {
+                self.summarize(kind, window);
+                *window = Window { started: now, suppressed: 0 };
+            }
+            None => {
+                kinds.insert(kind.to_string(), Window { started: now, suppressed: 0 });
+            }
+        }
+        warn!("{message}");
+    }
+
+    /// Logs the summaries of all pending windows
+    pub fn flush(&self) {
+        let kinds = std::mem::take(&mut *self.kinds.lock().unwrap());
+        for (kind, window) in kinds {
+            self.summarize(&kind, &window);
+        }
+    }
+
+    fn summarize(&self, kind: &str, window: &Window) {
+        if window.suppressed > 0 {
+            warn!("{kind} x{} more in the last {}s", window.suppressed, self.window.as_secs());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suppressed(aggregator: &WarningAggregator, kind: &str) -> Option<usize> {
+        aggregator.kinds.lock().unwrap().get(kind).map(|window| window.suppressed)
+    }
+
+    #[test]
+    fn counts_warnings_of_a_kind_within_the_window() {
+        let aggregator = WarningAggregator::new(Duration::from_secs(60));
+        aggregator.warn("Missing seeders: torrent 1");
+        aggregator.warn("Missing seeders: torrent 2");
+        aggregator.warn("Missing seeders: torrent 3");
+        aggregator.warn("Unknown category");
+        assert_eq!(suppressed(&aggregator, "Missing seeders"), Some(2));
+        assert_eq!(suppressed(&aggregator, "Unknown category"), Some(0));
+
+        aggregator.flush();
+        assert_eq!(suppressed(&aggregator, "Missing seeders"), None);
+    }
+
+    #[test]
+    fn starts_a_new_window_once_elapsed() {
+        let aggregator = WarningAggregator::new(Duration::ZERO);
+        aggregator.warn("Missing seeders: torrent 1");
+        aggregator.warn("Missing seeders: torrent 2");
+        assert_eq!(suppressed(&aggregator, "Missing seeders"), Some(0));
+    }
+}
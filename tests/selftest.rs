@@ -0,0 +1,47 @@
+//! Runs the binary, as exit codes can only be checked from outside of it
+
+use std::{io::{Read, Write}, net::TcpListener, process::Command};
+
+/// Serves a page to every request, closing each connection after it
+fn serve(page: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&buffer[..read]),
+                }
+            }
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", page.len());
+            let _ = stream.write_all(head.as_bytes()).and_then(|()| stream.write_all(page.as_bytes()));
+        }
+    });
+    url
+}
+
+fn selftest(page: String) -> std::process::ExitStatus {
+    let dir = std::env::temp_dir().join(format!("x1337x-scraper-selftest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_x1337x-scraper"))
+        .args(["selftest", "--ids", "5558130", "--retries", "0", "--base-url", &serve(page)])
+        .current_dir(&dir)
+        .status()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    status
+}
+
+#[test]
+fn broken_pages_fail_the_selftest() {
+    let page = include_str!("../test.html");
+    assert!(selftest(page.to_string()).success());
+
+    let broken = page.replace("<span>4738FB09F6AE3E2F45BCA0FFEC9F32779500AB03</span>", "<span>not a hash</span>");
+    assert_ne!(broken, page);
+    let status = selftest(broken);
+    assert_eq!(status.code(), Some(1));
+}